@@ -3,8 +3,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::libc::types::os::arch::c95::c_double;
-use std::cmp::Eq;
+use std::cmp::{Eq, Ordering, Less, Equal, Greater, lexical_ordering};
 use std::ascii::AsciiStr;
+use std::num;
+use std::vec;
+use std::float;
+use std::float::consts::pi;
+#[cfg(quickcheck)]
+use std::rand::{Rand, Rng};
+use std::to_bytes::{IterBytes, Cb};
 
 macro_rules! define_color(
     ($color:ident, $r:expr, $g:expr, $b:expr) => {
@@ -12,22 +19,7 @@ macro_rules! define_color(
     }
 )
 
-macro_rules! parse_static_color(
-    ($name:expr, $($color:ident),+) => {
-        {
-            let name = $name.trim().to_owned().into_ascii().to_upper().into_str();
-            let mut color = None;
-            $(
-                if (stringify!($color) == name) {
-                    color = Some($color);
-                }
-            )+
-            color
-        }
-    }
-)
-
-#[deriving(Eq)]
+#[deriving(Eq, Clone)]
 pub struct Color {
     red: u8,
     green: u8,
@@ -35,7 +27,53 @@ pub struct Color {
     alpha: float,
 }
 
+/** A display gamut that colors can be checked against */
+pub enum Gamut {
+    GamutSrgb,
+    /// Only neutral colors, as on a monochrome e-ink panel
+    GamutGrayscale,
+    /// Any RGB display, given the CIE xy chromaticities of its red, green and blue primaries
+    GamutPrimaries((float, float), (float, float), (float, float)),
+}
+
+/** The color spaces `Color::convert` can express a color in */
+#[deriving(Eq)]
+pub enum ColorSpace {
+    /// Gamma-encoded red, green and blue in 0.0..1.0
+    SrgbSpace,
+    /// Hue in degrees, saturation and lightness in 0.0..1.0
+    HslSpace,
+    /// Hue in degrees, whiteness and blackness in 0.0..1.0
+    HwbSpace,
+    /// CIE L*a*b* for a D65 white, L* in 0..100
+    LabSpace,
+    /// CIE L*a*b* in polar form, hue in degrees
+    LchSpace,
+    OklabSpace,
+    /// OKLab in polar form, hue in degrees
+    OklchSpace,
+}
+
+/** A color's components in a particular color space */
+#[deriving(Eq)]
+pub struct ColorValue {
+    space: ColorSpace,
+    components: (float, float, float),
+    alpha: float,
+}
+
+/** Every serialization of a color, as shown side by side in a devtools panel */
+pub struct ColorFormats {
+    hex: ~str,
+    rgb: ~str,
+    hsl: ~str,
+    oklch: ~str,
+    name: Option<~str>,
+}
+
+/** Alpha is clamped to 0.0..1.0 */
 pub fn rgba(r : u8, g : u8, b : u8, a : float) -> Color {
+    let a = if a < 0.0 { 0.0 } else if a > 1.0 { 1.0 } else { a };
     Color { red : r, green : g, blue : b, alpha : a}
 }
 
@@ -43,7 +81,30 @@ pub fn rgb(r : u8, g : u8, b : u8) -> Color {
     return rgba(r, g, b, 1.0);
 }
 
+fn finite(values : &[float]) -> bool {
+    // x - x is 0 for everything but NaN and the infinities
+    values.iter().all(|v| *v - *v == 0.0)
+}
+
+/** The inverse of `Color::to_rgba_tuple` */
+pub fn from_rgba_tuple(t : (u8, u8, u8, float)) -> Color {
+    let (r, g, b, a) = t;
+    return rgba(r, g, b, a);
+}
+
+/**
+Saturation and lightness are clamped to 0.0..1.0 and hue is taken modulo
+360. A NaN or infinite component gives black.
+*/
 pub fn hsla(h : float, s : float, l : float, a : float) -> Color {
+    if !finite([h, s, l]) {
+        return rgba(0, 0, 0, a);
+    }
+    let s = if s < 0.0 { 0.0 } else if s > 1.0 { 1.0 } else { s };
+    let l = if l < 0.0 { 0.0 } else if l > 1.0 { 1.0 } else { l };
+    let h = h % 360.0;
+    let h = if h < 0.0 { h + 360.0 } else { h };
+
     // Algorithm for converting hsl to rbg taken from
     // http://www.w3.org/TR/2003/CR-css3-color-20030514/#hsl-color
     let m2 = if l <= 0.5 { l*(s + 1.0) } else { l + s - l*s };
@@ -78,168 +139,2146 @@ pub fn hsl(h : float, s : float, l : float) -> Color {
     return hsla(h, s, l, 1.0);
 }
 
-impl Color {
-    fn print(&self) -> ~str {
+/**
+HSV (a.k.a. HSB): hue in degrees, saturation and value in 0.0..1.0. As with
+hsla, saturation and value are clamped, hue is taken modulo 360 and a NaN or
+infinite component gives black.
+*/
+pub fn hsva(h : float, s : float, v : float, a : float) -> Color {
+    if !finite([h, s, v]) {
+        return rgba(0, 0, 0, a);
+    }
+    let s = if s < 0.0 { 0.0 } else if s > 1.0 { 1.0 } else { s };
+    let v = if v < 0.0 { 0.0 } else if v > 1.0 { 1.0 } else { v };
+    let h = h % 360.0;
+    let h = (if h < 0.0 { h + 360.0 } else { h }) / 60.0;
+    let c = v*s;
+    let x = c*(1.0 - (h % 2.0 - 1.0).abs());
+    let m = v - c;
+
+    let (r, g, b) = if h < 1.0 { (c, x, 0.0) }
+        else if h < 2.0 { (x, c, 0.0) }
+        else if h < 3.0 { (0.0, c, x) }
+        else if h < 4.0 { (0.0, x, c) }
+        else if h < 5.0 { (x, 0.0, c) }
+        else { (c, 0.0, x) };
+
+    let r = (255.0*(r + m) as c_double).round();
+    let g = (255.0*(g + m) as c_double).round();
+    let b = (255.0*(b + m) as c_double).round();
+
+    return rgba(r as u8, g as u8, b as u8, a);
+}
+
+pub fn hsv(h : float, s : float, v : float) -> Color {
+    return hsva(h, s, v, 1.0);
+}
+
+/** HWB: hue in degrees, whiteness and blackness in 0.0..1.0 */
+pub fn hwba(h : float, w : float, b : float, a : float) -> Color {
+    let w = if w < 0.0 { 0.0 } else if w > 1.0 { 1.0 } else { w };
+    let b = if b < 0.0 { 0.0 } else if b > 1.0 { 1.0 } else { b };
+    // Whiteness and blackness that add up past 1 make a gray
+    let (w, b) = if w + b > 1.0 { (w / (w + b), b / (w + b)) } else { (w, b) };
+    let v = 1.0 - b;
+    hsva(h, if v == 0.0 { 0.0 } else { 1.0 - w / v }, v, a)
+}
+
+pub fn hwb(h : float, w : float, b : float) -> Color {
+    return hwba(h, w, b, 1.0);
+}
+
+/**
+Approximates the color of monochromatic light of wavelength `nm`, using
+Dan Bruton's piecewise fit over the visible range (380-780nm). Intensity
+falls off towards both ends of the spectrum; anything outside it is black.
+*/
+pub fn from_wavelength(nm : float) -> Color {
+    let (r, g, b) = if nm >= 380.0 && nm < 440.0 {
+        ((440.0 - nm) / (440.0 - 380.0), 0.0, 1.0)
+    } else if nm >= 440.0 && nm < 490.0 {
+        (0.0, (nm - 440.0) / (490.0 - 440.0), 1.0)
+    } else if nm >= 490.0 && nm < 510.0 {
+        (0.0, 1.0, (510.0 - nm) / (510.0 - 490.0))
+    } else if nm >= 510.0 && nm < 580.0 {
+        ((nm - 510.0) / (580.0 - 510.0), 1.0, 0.0)
+    } else if nm >= 580.0 && nm < 645.0 {
+        (1.0, (645.0 - nm) / (645.0 - 580.0), 0.0)
+    } else if nm >= 645.0 && nm <= 780.0 {
+        (1.0, 0.0, 0.0)
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+
+    let intensity = if nm >= 380.0 && nm < 420.0 {
+        0.3 + 0.7*(nm - 380.0) / (420.0 - 380.0)
+    } else if nm >= 420.0 && nm <= 700.0 {
+        1.0
+    } else if nm > 700.0 && nm <= 780.0 {
+        0.3 + 0.7*(780.0 - nm) / (780.0 - 700.0)
+    } else {
+        0.0
+    };
+
+    let encode = |c : float| unit_to_channel((c * intensity).pow(&0.8));
+    rgb(encode(r), encode(g), encode(b))
+}
+
+/** Expands a 16-bit RGB565 pixel, replicating high bits into the low ones */
+pub fn from_rgb565(v : u16) -> Color {
+    let r = (v >> 11) & 0x1f;
+    let g = (v >> 5) & 0x3f;
+    let b = v & 0x1f;
+    rgb((r << 3 | r >> 2) as u8, (g << 2 | g >> 4) as u8, (b << 3 | b >> 2) as u8)
+}
+
+impl ToStr for Color {
+    fn to_str(&self) -> ~str {
         fmt!("rgba(%u,%u,%u,%f)", self.red as uint, self.green as uint,
              self.blue as uint, self.alpha)
     }
 }
 
-pub mod parsing {
-    use super::*;
-    use super::{Color, rgb, rgba, hsl, hsla};
+/**
+Random colors for property tests. Half are opaque, since that is the common
+case; the rest get an alpha anywhere in 0.0..1.0. Only built with
+`--cfg quickcheck`, which `make check QUICKCHECK=1` passes.
+*/
+#[cfg(quickcheck)]
+impl Rand for Color {
+    fn rand<R: Rng>(rng : &mut R) -> Color {
+        let alpha = if rng.gen() { 1.0 } else { rng.gen() };
+        rgba(rng.gen(), rng.gen(), rng.gen(), alpha)
+    }
+}
 
-    /** Parses a color specification in the form rgb(foo,bar,baz) */
-    fn parse_rgb(color : &str) -> Option<Color> {
-        // Shave off the rgb( and the )
-        let only_colors = color.slice(4u, color.len() - 1);
-
-        // split up r, g, and b
-        let mut cols = ~[];
-        for s in only_colors.split_iter(',') {
-            cols.push(s.trim());
+/**
+Colors order by red, then green, then blue, then alpha, so sorting a palette
+gives the same result every time. A NaN alpha sorts before any other.
+*/
+impl TotalOrd for Color {
+    fn cmp(&self, other: &Color) -> Ordering {
+        let rgb = (self.red, self.green, self.blue).cmp(&(other.red, other.green, other.blue));
+        lexical_ordering(rgb, cmp_alpha(self.alpha, other.alpha))
+    }
+}
+
+impl TotalEq for Color {
+    fn equals(&self, other: &Color) -> bool {
+        self.cmp(other) == Equal
+    }
+}
+
+impl Ord for Color {
+    fn lt(&self, other: &Color) -> bool {
+        self.cmp(other) == Less
+    }
+}
+
+/**
+Hashes the channels with alpha scaled to a byte, so colors that are equal
+always hash the same and a Color can key a HashMap
+*/
+impl IterBytes for Color {
+    fn iter_bytes(&self, lsb0: bool, f: Cb) -> bool {
+        self.to_rgba_bytes().iter_bytes(lsb0, f)
+    }
+}
+
+fn cmp_alpha(a : float, b : float) -> Ordering {
+    match (a != a, b != b) {
+        (true, true) => Equal,
+        (true, false) => Less,
+        (false, true) => Greater,
+        (false, false) => if a < b { Less } else if a > b { Greater } else { Equal }
+    }
+}
+
+impl Color {
+    fn print(&self) -> ~str {
+        self.to_str()
+    }
+
+    /**
+    Serializes the color the way a person would write it: the CSS keyword
+    if one matches exactly, otherwise the shortest hex form
+    */
+    pub fn to_named_or_hex(&self) -> ~str {
+        if *self == rgba(0, 0, 0, 0.0) {
+            return ~"transparent";
+        }
+        match name_of(self) {
+            Some(name) => name,
+            None => self.to_hex_minimal()
+        }
+    }
+
+    /**
+    Multiplies the normalized RGB vector by `m` (row-major, so each row
+    produces one output channel) and clamps the result. Alpha is preserved.
+    This is the primitive behind the CSS filter functions.
+    */
+    pub fn apply_matrix(&self, m : [[float, ..3], ..3]) -> Color {
+        let v = [self.red as float / 255.0,
+                 self.green as float / 255.0,
+                 self.blue as float / 255.0];
+        let row = |i : uint| m[i][0]*v[0] + m[i][1]*v[1] + m[i][2]*v[2];
+        rgba(unit_to_channel(row(0)), unit_to_channel(row(1)),
+             unit_to_channel(row(2)), self.alpha)
+    }
+
+    /**
+    Rotates the hue the way `filter: hue-rotate(deg)` does. This uses the
+    luminance-preserving matrix from the Filter Effects spec, which does not
+    give the same result as rotating the HSL hue.
+    */
+    pub fn hue_rotate_filter(&self, degrees : float) -> Color {
+        let angle = degrees * pi / 180.0;
+        let (c, s) = (angle.cos(), angle.sin());
+        self.apply_matrix([
+            [0.213 + c*0.787 - s*0.213, 0.715 - c*0.715 - s*0.715, 0.072 - c*0.072 + s*0.928],
+            [0.213 - c*0.213 + s*0.143, 0.715 + c*0.285 + s*0.140, 0.072 - c*0.072 - s*0.283],
+            [0.213 - c*0.213 - s*0.787, 0.715 - c*0.715 + s*0.715, 0.072 + c*0.928 + s*0.072]
+        ])
+    }
+
+    /**
+    Adjusts saturation the way `filter: saturate(amount)` does: 1.0 is the
+    identity, 0.0 is Rec. 709 grayscale and larger values oversaturate.
+    Unlike an HSL saturation change this is a luminance-weighted matrix.
+    */
+    pub fn saturate_filter(&self, amount : float) -> Color {
+        let s = amount;
+        self.apply_matrix([
+            [0.213 + 0.787*s, 0.715 - 0.715*s, 0.072 - 0.072*s],
+            [0.213 - 0.213*s, 0.715 + 0.285*s, 0.072 - 0.072*s],
+            [0.213 - 0.213*s, 0.715 - 0.715*s, 0.072 + 0.928*s]
+        ])
+    }
+
+    /** Serializes the color in every supported notation at once */
+    pub fn to_formats(&self) -> ColorFormats {
+        let (h, s, l) = self.to_hsl_rounded();
+        let (ok_l, ok_c, ok_h) = self.to_oklch();
+        let (rgb, hsl) = if self.alpha < 1.0 {
+            (fmt!("rgba(%u, %u, %u, %s)", self.red as uint, self.green as uint,
+                  self.blue as uint, self.alpha.to_str()),
+             fmt!("hsla(%u, %u%%, %u%%, %s)", h as uint, s as uint, l as uint,
+                  self.alpha.to_str()))
+        } else {
+            (fmt!("rgb(%u, %u, %u)", self.red as uint, self.green as uint,
+                  self.blue as uint),
+             fmt!("hsl(%u, %u%%, %u%%)", h as uint, s as uint, l as uint))
         };
+        ColorFormats {
+            hex: self.to_hex(),
+            rgb: rgb,
+            hsl: hsl,
+            oklch: fmt!("oklch(%.2f%% %.3f %.2f)", ok_l*100.0, ok_c, ok_h),
+            name: name_of(self),
+        }
+    }
 
-        if cols.len() != 3u { return fail_unrecognized(color); }
+    /**
+    The HSL form color pickers show: whole degrees of hue in 0..359 and
+    whole percentages of saturation and lightness
+    */
+    pub fn to_hsl_rounded(&self) -> (u16, u8, u8) {
+        let (h, s, l) = self.to_hsl();
+        ((round_uint(h) % 360) as u16, round_uint(s*100.0) as u8, round_uint(l*100.0) as u8)
+    }
 
-        match (FromStr::from_str(cols[0]), FromStr::from_str(cols[1]), 
-               FromStr::from_str(cols[2])) {
-          (Some(r), Some(g), Some(b)) => { Some(rgb(r, g, b)) }
-          _ => { fail_unrecognized(color) }
+    /** Hue in degrees, saturation and lightness in 0.0..1.0 */
+    pub fn to_hsl(&self) -> (float, float, float) {
+        let r = self.red as float / 255.0;
+        let g = self.green as float / 255.0;
+        let b = self.blue as float / 255.0;
+        let max = r.max(&g).max(&b);
+        let min = r.min(&g).min(&b);
+        let l = (max + min) / 2.0;
+        if max == min {
+            return (0.0, 0.0, l);
         }
+
+        let d = max - min;
+        let s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+        let h = if max == r {
+            (g - b) / d + if g < b { 6.0 } else { 0.0 }
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        };
+        (h * 60.0, s, l)
     }
 
-    /** Parses a color specification in the form rgba(foo,bar,baz,qux) */
-    fn parse_rgba(color : &str) -> Option<Color> {
-        // Shave off the rgba( and the )
-        let only_vals = color.slice(5u, color.len() - 1);
-
-        // split up r, g, and b
-        let mut cols = ~[];
-        for s in only_vals.split_iter(',') {
-            cols.push(s);
+    /** Raises HSL lightness by `amount`, clamped to 1.0. Alpha is kept. */
+    pub fn lighten(&self, amount : float) -> Color {
+        let (h, s, l) = self.to_hsl();
+        let l = l + amount;
+        hsla(h, s, if l > 1.0 { 1.0 } else if l < 0.0 { 0.0 } else { l }, self.alpha)
+    }
+
+    /** Lowers HSL lightness by `amount`, clamped to 0.0. Alpha is kept. */
+    pub fn darken(&self, amount : float) -> Color {
+        self.lighten(-amount)
+    }
+
+    /** Raises HSL saturation by `amount`, clamped to 1.0. Alpha is kept. */
+    pub fn saturate(&self, amount : float) -> Color {
+        let (h, s, l) = self.to_hsl();
+        let s = s + amount;
+        hsla(h, if s > 1.0 { 1.0 } else if s < 0.0 { 0.0 } else { s }, l, self.alpha)
+    }
+
+    /**
+    Lowers HSL saturation by `amount`, clamped to 0.0. Desaturating fully
+    gives the gray at the color's HSL lightness. Alpha is kept.
+    */
+    pub fn desaturate(&self, amount : float) -> Color {
+        self.saturate(-amount)
+    }
+
+    /**
+    Turns the HSL hue by `degrees`, either way round the wheel, keeping
+    saturation, lightness and alpha. Unlike `hue_rotate_filter` this is an
+    exact rotation, as used for complementary and triadic palettes.
+    */
+    pub fn rotate_hue(&self, degrees : float) -> Color {
+        let (h, s, l) = self.to_hsl();
+        let h = (h + degrees) % 360.0;
+        hsla(if h < 0.0 { h + 360.0 } else { h }, s, l, self.alpha)
+    }
+
+    /** The opposite hue on the HSL color wheel. Grays are their own complement. */
+    pub fn complement(&self) -> Color {
+        self.rotate_hue(180.0)
+    }
+
+    /**
+    Blends towards `other` like Sass's mix(): every channel and alpha is
+    interpolated linearly, so `weight` 0.0 gives this color and 1.0 gives
+    `other`
+    */
+    pub fn mix(&self, other : &Color, weight : float) -> Color {
+        let lerp = |a : u8, b : u8| {
+            ((a as float + (b as float - a as float)*weight) as c_double).round() as u8
         };
+        rgba(lerp(self.red, other.red), lerp(self.green, other.green),
+             lerp(self.blue, other.blue), self.alpha + (other.alpha - self.alpha)*weight)
+    }
+
+    /**
+    Composites this color over `background` in linear light: both are
+    linearized, blended by alpha and re-encoded. Blending the gamma-encoded
+    values directly darkens soft edges, which high-quality renderers avoid.
+    */
+    pub fn over_linear(&self, background : &Color) -> Color {
+        let a = self.alpha;
+        let out_alpha = a + background.alpha*(1.0 - a);
+        if out_alpha == 0.0 {
+            return rgba(0, 0, 0, 0.0);
+        }
 
-        if cols.len() != 4u { return fail_unrecognized(color); }
+        let blend = |fg : u8, bg : u8| {
+            let fg = srgb_to_linear(fg as float / 255.0);
+            let bg = srgb_to_linear(bg as float / 255.0);
+            let mixed = (fg*a + bg*background.alpha*(1.0 - a)) / out_alpha;
+            unit_to_channel(linear_to_srgb(mixed))
+        };
+        rgba(blend(self.red, background.red),
+             blend(self.green, background.green),
+             blend(self.blue, background.blue),
+             out_alpha)
+    }
 
-        match (FromStr::from_str(cols[0]), FromStr::from_str(cols[1]), 
-               FromStr::from_str(cols[2]), FromStr::from_str(cols[3])) {
-          (Some(r), Some(g), Some(b), Some(a)) => { Some(rgba(r, g, b, a)) }
-          _ => { fail_unrecognized(color) }
+    /**
+    Searches the lightness range of the complementary hue for the shade
+    contrasting most with this color, for accent-on-accent UI. The search
+    stops short of pure black and white so the result keeps its hue.
+    */
+    pub fn max_contrast_complement(&self) -> Color {
+        let (h, s, _) = self.to_hsl();
+        let h = (h + 180.0) % 360.0;
+        let mut best = hsla(h, s, 0.05, self.alpha);
+        let mut best_ratio = self.contrast_ratio(&best);
+        for step in range(6u, 96u) {
+            let candidate = hsla(h, s, step as float / 100.0, self.alpha);
+            let ratio = self.contrast_ratio(&candidate);
+            if ratio > best_ratio {
+                best = candidate;
+                best_ratio = ratio;
+            }
         }
+        best
     }
 
-    /** Parses a color specification in the form hsl(foo,bar,baz) */
-    fn parse_hsl(color : &str) -> Option<Color> {
-        // Shave off the hsl( and the )
-        let only_vals = color.slice(4u, color.len() - 1);
-
-        // split up h, s, and l
-        let mut vals = ~[];
-        for s in only_vals.split_iter(',') {
-            vals.push(s);
+    /**
+    Black or white, whichever is more readable on this color once it has
+    been flattened over `bg`. Deciding on the flattened color matters for
+    translucent surfaces, whose raw channels can be misleading.
+    */
+    pub fn on_color(&self, bg : &Color) -> Color {
+        let surface = self.over(bg);
+        let black = rgb(0, 0, 0);
+        let white = rgb(255, 255, 255);
+        if surface.contrast_ratio(&black) >= surface.contrast_ratio(&white) { black } else { white }
+    }
+
+    /**
+    Source-over compositing of gamma-encoded channels. The result is opaque
+    whenever the background is.
+    */
+    pub fn over(&self, background : &Color) -> Color {
+        let a = self.alpha;
+        let out_alpha = a + background.alpha*(1.0 - a);
+        if out_alpha == 0.0 {
+            return rgba(0, 0, 0, 0.0);
+        }
+        let blend = |fg : u8, bg : u8| {
+            let mixed = (fg as float*a + bg as float*background.alpha*(1.0 - a)) / out_alpha;
+            unit_to_channel(mixed / 255.0)
         };
+        rgba(blend(self.red, background.red),
+             blend(self.green, background.green),
+             blend(self.blue, background.blue),
+             out_alpha)
+    }
 
-        if vals.len() != 3u { return fail_unrecognized(color); }
+    /**
+    Scales the channels by alpha for renderers that work in premultiplied
+    form. Alpha itself is kept.
+    */
+    pub fn premultiply(&self) -> Color {
+        let scale = |c : u8| unit_to_channel(c as float / 255.0 * self.alpha);
+        rgba(scale(self.red), scale(self.green), scale(self.blue), self.alpha)
+    }
 
-        match (FromStr::from_str(vals[0]), FromStr::from_str(vals[1]), 
-               FromStr::from_str(vals[2])) {
-          (Some(h), Some(s), Some(l)) => { Some(hsl(h, s, l)) }
-          _ => { fail_unrecognized(color) }
+    /**
+    The inverse of `premultiply`. A fully transparent color carries no
+    channel information, so it comes back as transparent black.
+    */
+    pub fn unpremultiply(&self) -> Color {
+        if self.alpha == 0.0 {
+            return rgba(0, 0, 0, 0.0);
         }
+        let scale = |c : u8| unit_to_channel(c as float / 255.0 / self.alpha);
+        rgba(scale(self.red), scale(self.green), scale(self.blue), self.alpha)
     }
 
-    /** Parses a color specification in the form hsla(foo,bar,baz,qux) */
-    fn parse_hsla(color : &str) -> Option<Color> {
-        // Shave off the hsla( and the )
-        let only_vals = color.slice(5u, color.len() - 1);
+    /**
+    The gray with the same luminance as this color, so it looks as bright
+    as the original rather than as bright as its channel average. Alpha is
+    kept.
+    */
+    pub fn grayscale(&self) -> Color {
+        let gray = unit_to_channel(linear_to_srgb(self.luminance()));
+        rgba(gray, gray, gray, self.alpha)
+    }
+
+    /** Whether `luminance()` is above 0.5 */
+    pub fn is_light(&self) -> bool {
+        self.luminance() > 0.5
+    }
+
+    /** Whether `luminance()` is 0.5 or below; the opposite of `is_light` */
+    pub fn is_dark(&self) -> bool {
+        !self.is_light()
+    }
+
+    /** WCAG contrast ratio against `other`, from 1.0 to 21.0. Body text needs 4.5. */
+    pub fn contrast_ratio(&self, other : &Color) -> float {
+        let a = self.luminance();
+        let b = other.luminance();
+        let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
+    /**
+    The entry of `palette` closest to this color by RGB distance; the first
+    one wins a tie. Fails on an empty palette.
+    */
+    pub fn map_to_palette(&self, palette : &[Color]) -> Color {
+        assert!(!palette.is_empty());
+        let mut best = palette[0];
+        let mut best_distance = self.rgb_distance_sq(&best);
+        for candidate in palette.iter() {
+            let distance = self.rgb_distance_sq(candidate);
+            if distance < best_distance {
+                best = *candidate;
+                best_distance = distance;
+            }
+        }
+        best
+    }
+
+    /**
+    The CSS keyword for the named color closest by RGB distance. Alpha is
+    ignored. Ties go to the alphabetically first keyword, so `aqua` wins
+    over `cyan`.
+    */
+    pub fn nearest_named(&self) -> ~str {
+        let (mut best, first) = NAMED_COLORS[0];
+        let mut best_distance = self.rgb_distance_sq(&unpack_rgb(first));
+        for &(name, packed) in NAMED_COLORS.iter() {
+            let distance = self.rgb_distance_sq(&unpack_rgb(packed));
+            if distance < best_distance {
+                best = name;
+                best_distance = distance;
+            }
+        }
+        best.to_owned()
+    }
+
+    fn rgb_distance_sq(&self, other : &Color) -> int {
+        let dr = self.red as int - other.red as int;
+        let dg = self.green as int - other.green as int;
+        let db = self.blue as int - other.blue as int;
+        dr*dr + dg*dg + db*db
+    }
+
+    /**
+    The old W3C "color brightness" difference, using the integer brightness
+    (R*299 + G*587 + B*114) / 1000. Legacy audits want at least 125.
+    */
+    pub fn brightness_difference(&self, other : &Color) -> u16 {
+        let brightness = |c : &Color|
+            (c.red as int * 299 + c.green as int * 587 + c.blue as int * 114) / 1000;
+        num::abs(brightness(self) - brightness(other)) as u16
+    }
+
+    /**
+    The old W3C "color difference", the sum of the per-channel differences.
+    Legacy audits want at least 500.
+    */
+    pub fn color_difference(&self, other : &Color) -> u16 {
+        (num::abs(self.red as int - other.red as int) +
+         num::abs(self.green as int - other.green as int) +
+         num::abs(self.blue as int - other.blue as int)) as u16
+    }
+
+    /**
+    Whether every channel is within `channel_tol` of `other`'s and alpha is
+    within `alpha_tol`. See also `same_after_rounding`.
+    */
+    pub fn approx_eq(&self, other : &Color, channel_tol : u8, alpha_tol : float) -> bool {
+        let close = |x : u8, y : u8| num::abs(x as int - y as int) <= channel_tol as int;
+        close(self.red, other.red) && close(self.green, other.green) &&
+            close(self.blue, other.blue) && num::abs(self.alpha - other.alpha) <= alpha_tol
+    }
+
+    /** The same color with alpha replaced by `a`, clamped to 0.0..1.0 */
+    pub fn with_alpha(&self, a : float) -> Color {
+        rgba(self.red, self.green, self.blue, a)
+    }
+
+    /**
+    Recovers the intended opacity of a color whose alpha was copied straight
+    from an 8-bit source (e.g. 128.0 instead of 0.502). Alphas already in
+    0.0..1.0 are left alone.
+    */
+    pub fn normalize_alpha_from_byte(&self) -> Color {
+        if self.alpha <= 1.0 {
+            return *self;
+        }
+        let a = self.alpha / 255.0;
+        rgba(self.red, self.green, self.blue, if a > 1.0 { 1.0 } else { a })
+    }
+
+    /** Whether this is an additive primary: red, green (lime) or blue */
+    pub fn is_primary(&self) -> bool {
+        self.full_channels() == Some(1)
+    }
+
+    /** Whether this is an additive secondary: cyan, magenta or yellow */
+    pub fn is_secondary(&self) -> bool {
+        self.full_channels() == Some(2)
+    }
+
+    /** How many channels are at 255, or None if any channel is partly on */
+    fn full_channels(&self) -> Option<uint> {
+        let mut full = 0u;
+        for c in [self.red, self.green, self.blue].iter() {
+            match *c {
+                255 => full += 1,
+                0 => (),
+                _ => return None
+            }
+        }
+        Some(full)
+    }
+
+    /** Packs the color into a 16-bit RGB565 pixel, rounding each channel */
+    pub fn to_rgb565(&self) -> u16 {
+        let scale = |c : u8, max : uint| ((c as uint * max + 127) / 255) as u16;
+        scale(self.red, 31) << 11 | scale(self.green, 63) << 5 | scale(self.blue, 31)
+    }
+
+    /**
+    Relative luminance as defined by WCAG, in 0.0..1.0. WCAG linearizes
+    below 0.03928 rather than the sRGB spec's 0.04045, but no 8-bit channel
+    value falls between the two, so the result is the same.
+    */
+    pub fn luminance(&self) -> float {
+        0.2126*srgb_to_linear(self.red as float / 255.0) +
+        0.7152*srgb_to_linear(self.green as float / 255.0) +
+        0.0722*srgb_to_linear(self.blue as float / 255.0)
+    }
+
+    /** Perceptual distance to `other`: Euclidean distance in OKLab */
+    pub fn delta_e(&self, other : &Color) -> float {
+        let (l1, a1, b1) = self.to_oklab();
+        let (l2, a2, b2) = other.to_oklab();
+        ((l1 - l2)*(l1 - l2) + (a1 - a2)*(a1 - a2) + (b1 - b2)*(b1 - b2)).sqrt()
+    }
+
+    /**
+    The conventional disabled-control look: chroma is cut to 30% and OKLab
+    lightness is pulled halfway towards 0.6 (the lightness of a 50% sRGB
+    gray), so contrast drops but a hint of the hue remains. Alpha is kept.
+    */
+    pub fn muted(&self) -> Color {
+        let (l, a, b) = self.to_oklab();
+        from_oklab(l + (0.6 - l)*0.5, a*0.3, b*0.3, self.alpha)
+    }
+
+    /**
+    `count` shades of this color's OKLCh hue, from dark (OKLab lightness
+    0.25) to light (0.9). Chroma tapers away from the color's own lightness
+    and is cut further where a shade would leave the sRGB gamut, so the hue
+    holds steady instead of drifting when channels clip. Alpha is kept.
+    */
+    pub fn monochromatic(&self, count : uint) -> ~[Color] {
+        match count {
+            0 => return ~[],
+            1 => return ~[*self],
+            _ => ()
+        }
+
+        let (l0, c0, h) = self.to_oklch();
+        vec::from_fn(count, |i| {
+            let l = 0.25 + 0.65 * i as float / (count - 1) as float;
+            from_oklch_in_gamut(l, c0 * (1.0 - 0.5*(l - l0).abs()), h, self.alpha)
+        })
+    }
+
+    /**
+    This color recolored to OKLCh hue `hue_degrees`, keeping its lightness
+    and as much of its chroma as fits in sRGB. Grays stay gray.
+    */
+    pub fn project_to_hue(&self, hue_degrees : float) -> Color {
+        let (l, c, _) = self.to_oklch();
+        from_oklch_in_gamut(l, c, hue_degrees, self.alpha)
+    }
+
+    /** CIE 1976 L*u*v* coordinates, relative to a D65 white */
+    pub fn to_luv(&self) -> (float, float, float) {
+        let (x, y, z) = self.to_xyz();
+        let yr = y / D65_Y;
+        let l = if yr > 216.0 / 24389.0 { 116.0 * yr.cbrt() - 16.0 } else { 24389.0 / 27.0 * yr };
+        let d = x + 15.0*y + 3.0*z;
+        if d == 0.0 {
+            return (l, 0.0, 0.0);
+        }
+        let (un, vn) = white_uv();
+        (l, 13.0*l*(4.0*x/d - un), 13.0*l*(9.0*y/d - vn))
+    }
+
+    /** L*u*v* in polar form: lightness, chroma and hue in degrees */
+    pub fn to_lchuv(&self) -> (float, float, float) {
+        let (l, u, v) = self.to_luv();
+        let h = v.atan2(&u) * 180.0 / pi;
+        (l, (u*u + v*v).sqrt(), if h < 0.0 { h + 360.0 } else { h })
+    }
+
+    /**
+    Whether showing this color on a display with the given gamut would
+    clip it. Only chromaticity is compared; the display is assumed to share
+    the D65 white point and cover the full luminance range.
+    */
+    pub fn clips_in(&self, gamut : Gamut) -> bool {
+        let (x, y, z) = self.to_xyz();
+        let sum = x + y + z;
+        if sum == 0.0 {
+            // Black fits anywhere
+            return false;
+        }
+        let p = (x / sum, y / sum);
+
+        match gamut {
+            GamutSrgb => !in_triangle(p, (0.64, 0.33), (0.30, 0.60), (0.15, 0.06)),
+            GamutGrayscale => {
+                let (wx, wy) = (D65_X / (D65_X + D65_Y + D65_Z), D65_Y / (D65_X + D65_Y + D65_Z));
+                let (px, py) = p;
+                (px - wx).abs() > 1e-4 || (py - wy).abs() > 1e-4
+            }
+            GamutPrimaries(r, g, b) => !in_triangle(p, r, g, b)
+        }
+    }
+
+    /** Linear-light CIE XYZ for a D65 white */
+    fn to_xyz(&self) -> (float, float, float) {
+        let r = srgb_to_linear(self.red as float / 255.0);
+        let g = srgb_to_linear(self.green as float / 255.0);
+        let b = srgb_to_linear(self.blue as float / 255.0);
+        (0.4124564*r + 0.3575761*g + 0.1804375*b,
+         0.2126729*r + 0.7151522*g + 0.0721750*b,
+         0.0193339*r + 0.1191920*g + 0.9503041*b)
+    }
+
+    /** Coordinates in Björn Ottosson's OKLab space */
+    fn to_oklab(&self) -> (float, float, float) {
+        let r = srgb_to_linear(self.red as float / 255.0);
+        let g = srgb_to_linear(self.green as float / 255.0);
+        let b = srgb_to_linear(self.blue as float / 255.0);
+
+        let l = (0.4122214708*r + 0.5363325363*g + 0.0514459929*b).cbrt();
+        let m = (0.2119034982*r + 0.6806995451*g + 0.1073969566*b).cbrt();
+        let s = (0.0883024619*r + 0.2817188376*g + 0.6299787005*b).cbrt();
+
+        (0.2104542553*l + 0.7936177850*m - 0.0040720468*s,
+         1.9779984951*l - 2.4285922050*m + 0.4505937099*s,
+         0.0259040371*l + 0.7827717662*m - 0.8086757660*s)
+    }
+
+    /**
+    How colorful the color looks: its OKLCh chroma. Unlike HSL saturation
+    this is perceptual, so it suits sorting a palette by vividness. Grays
+    are 0 and the most vivid sRGB colors reach about 0.32.
+    */
+    pub fn chroma(&self) -> float {
+        let (_, c, _) = self.to_oklch();
+        c
+    }
+
+    /**
+    This color's components in `space`. `ColorValue::to_color` converts
+    back, so this doubles as a hub for moving between any two spaces.
+    */
+    pub fn convert(&self, space : ColorSpace) -> ColorValue {
+        let components = match space {
+            SrgbSpace => (self.red as float / 255.0, self.green as float / 255.0,
+                          self.blue as float / 255.0),
+            HslSpace => self.to_hsl(),
+            HwbSpace => {
+                let (h, _, _) = self.to_hsl();
+                let r = self.red as float / 255.0;
+                let g = self.green as float / 255.0;
+                let b = self.blue as float / 255.0;
+                (h, r.min(&g).min(&b), 1.0 - r.max(&g).max(&b))
+            }
+            LabSpace => self.to_lab(),
+            LchSpace => {
+                let (l, a, b) = self.to_lab();
+                let h = b.atan2(&a) * 180.0 / pi;
+                (l, (a*a + b*b).sqrt(), if h < 0.0 { h + 360.0 } else { h })
+            }
+            OklabSpace => self.to_oklab(),
+            OklchSpace => self.to_oklch()
+        };
+        ColorValue { space: space, components: components, alpha: self.alpha }
+    }
 
-        let mut vals = ~[];
-        for s in only_vals.split_iter(',') {
-            vals.push(s);
+    /** CIE L*a*b* coordinates, relative to a D65 white */
+    fn to_lab(&self) -> (float, float, float) {
+        let (x, y, z) = self.to_xyz();
+        let f = |t : float| {
+            if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
         };
+        let (fx, fy, fz) = (f(x / D65_X), f(y / D65_Y), f(z / D65_Z));
+        (116.0*fy - 16.0, 500.0*(fx - fy), 200.0*(fy - fz))
+    }
 
-        if vals.len() != 4u { return fail_unrecognized(color); }
+    /** OKLab in polar form: lightness, chroma and hue in degrees */
+    fn to_oklch(&self) -> (float, float, float) {
+        let (l, a, b) = self.to_oklab();
+        let c = (a*a + b*b).sqrt();
+        let h = b.atan2(&a) * 180.0 / pi;
+        (l, c, if h < 0.0 { h + 360.0 } else { h })
+    }
 
-        match (FromStr::from_str(vals[0]), FromStr::from_str(vals[1]), 
-               FromStr::from_str(vals[2]), FromStr::from_str(vals[3])) {
-          (Some(h), Some(s), Some(l), Some(a)) => { Some(hsla(h, s, l, a)) }
-          _ => { fail_unrecognized(color) }
+    /** Full-length #rrggbb, or #rrggbbaa when the color is translucent */
+    pub fn to_hex(&self) -> ~str {
+        if self.alpha < 1.0 {
+            fmt!("#%02x%02x%02x%02x", self.red as uint, self.green as uint,
+                 self.blue as uint, alpha_to_byte(self.alpha) as uint)
+        } else {
+            fmt!("#%02x%02x%02x", self.red as uint, self.green as uint, self.blue as uint)
         }
     }
 
-    // Currently colors are supported in rgb(a,b,c) form and also by
-    // keywords for several common colors.
-    // TODO: extend this
-    pub fn parse_color(color : &str) -> Option<Color> {
-        match color {
-          c if c.starts_with("rgb(") => parse_rgb(c),
-          c if c.starts_with("rgba(") => parse_rgba(c),
-          c if c.starts_with("hsl(") => parse_hsl(c),
-          c if c.starts_with("hsla(") => parse_hsla(c),
-          c => parse_by_name(c)
+    /** Always #rrggbbaa, even for opaque colors */
+    pub fn to_hex_alpha(&self) -> ~str {
+        fmt!("#%02x%02x%02x%02x", self.red as uint, self.green as uint,
+             self.blue as uint, alpha_to_byte(self.alpha) as uint)
+    }
+
+    /** The channels in red, green, blue, alpha order */
+    pub fn to_rgba_tuple(&self) -> (u8, u8, u8, float) {
+        (self.red, self.green, self.blue, self.alpha)
+    }
+
+    /**
+    Packed RGBA8 pixel data. Alpha is scaled to 0..255 and rounded to nearest,
+    halves away from zero, so 0.5 becomes 128
+    */
+    pub fn to_rgba_bytes(&self) -> [u8, ..4] {
+        [self.red, self.green, self.blue, alpha_to_byte(self.alpha)]
+    }
+
+    /** Alpha-first #aarrggbb, as Android and WPF expect */
+    pub fn to_argb_hex(&self) -> ~str {
+        fmt!("#%02x%02x%02x%02x", alpha_to_byte(self.alpha) as uint,
+             self.red as uint, self.green as uint, self.blue as uint)
+    }
+
+    /** A custom property declaration for this color, e.g. `--brand: red;` */
+    pub fn to_custom_property(&self, name : &str) -> ~str {
+        fmt!("--%s: %s;", name, self.to_named_or_hex())
+    }
+
+    /** Shortest hex form: #rgb when every channel repeats its nibble, else #rrggbb */
+    fn to_hex_minimal(&self) -> ~str {
+        let mut bytes = ~[self.red, self.green, self.blue];
+        if self.alpha < 1.0 {
+            bytes.push(alpha_to_byte(self.alpha));
+        }
+
+        let short = bytes.iter().all(|b| *b >> 4 == *b & 0xf);
+        let mut hex = ~"#";
+        for b in bytes.iter() {
+            if short {
+                hex.push_str(fmt!("%x", (*b & 0xf) as uint));
+            } else {
+                hex.push_str(fmt!("%02x", *b as uint));
+            }
         }
+        hex
     }
 }
 
-pub fn fail_unrecognized(col : &str) -> Option<Color> {
-    warn!("Unrecognized color %s", col);
-    return None;
+/** Scales a 0.0..1.0 channel to a byte, clamping and rounding to nearest */
+fn unit_to_channel(v : float) -> u8 {
+    let v = if v < 0.0 { 0.0 } else if v > 1.0 { 1.0 } else { v };
+    (255.0*v as c_double).round() as u8
 }
 
-pub fn parse_by_name(name : &str) -> Option<Color> {
-    let color = parse_static_color!(name,
-            ALICEBLUE, ANTIQUEWHITE, AQUA, AQUAMARINE, AZURE,
-            BEIGE, BISQUE, BLACK, BLANCHEDALMOND, BLUE,
-            BLUEVIOLET, BROWN, BURLYWOOD, CADETBLUE, CHARTREUSE, 
-            CHOCOLATE, CORAL, CORNFLOWERBLUE, CORNSILK, CRIMSON,
-            CYAN, DARKBLUE, DARKCYAN, DARKGOLDENROD, DARKGRAY,
-            DARKGREEN, DARKGREY, DARKKHAKI, DARKMAGENTA, DARKOLIVEGREEN,
-            DARKORANGE, DARKORCHID, DARKRED, DARKSALMON, DARKSEAGREEN,
-            DARKSLATEBLUE, DARKSLATEGRAY, DARKSLATEGREY, DARKTURQUOISE, DARKVIOLET,
-            DEEPPINK, DEEPSKYBLUE, DIMGRAY, DIMGREY, DODGERBLUE,
-            FIREBRICK, FLORALWHITE, FORESTGREEN, FUCHSIA, GAINSBORO,
-            GHOSTWHITE, GOLD, GOLDENROD, GRAY, GREY,
-            GREEN, GREENYELLOW, HONEYDEW, HOTPINK, INDIANRED,
-            INDIGO, IVORY, KHAKI, LAVENDER, LAVENDERBLUSH,
-            LAWNGREEN, LEMONCHIFFON, LIGHTBLUE, LIGHTCORAL, LIGHTCYAN,
-            LIGHTGOLDENRODYELLOW, LIGHTGRAY, LIGHTGREEN, LIGHTGREY, LIGHTPINK,
-            LIGHTSALMON, LIGHTSEAGREEN, LIGHTSKYBLUE, LIGHTSLATEGRAY, LIGHTSLATEGREY,
-            LIGHTSTEELBLUE, LIGHTYELLOW, LIME, LIMEGREEN, LINEN,
-            MAGENTA, MAROON, MEDIUMAQUAMARINE, MEDIUMBLUE, MEDIUMORCHID,
-            MEDIUMPURPLE, MEDIUMSEAGREEN, MEDIUMSLATEBLUE, MEDIUMSPRINGGREEN, MEDIUMTURQUOISE,
-            MEDIUMVIOLETRED, MIDNIGHTBLUE, MINTCREAM, MISTYROSE, MOCCASIN,
-            NAVAJOWHITE, NAVY, OLDLACE, OLIVE, OLIVEDRAB,
-            ORANGE, ORANGERED, ORCHID, PALEGOLDENROD, PALEGREEN,
-            PALETURQUOISE, PALEVIOLETRED, PAPAYAWHIP, PEACHPUFF, PERU,
-            PINK, PLUM, POWDERBLUE, PURPLE, RED,
-            ROSYBROWN, ROYALBLUE, SADDLEBROWN, SALMON, SANDYBROWN,
-            SEAGREEN, SEASHELL, SIENNA, SILVER, SKYBLUE,
-            SLATEBLUE, SLATEGRAY, SLATEGREY, SNOW, SPRINGGREEN,
-            STEELBLUE, TAN, TEAL, THISTLE, TOMATO,
-            TURQUOISE, VIOLET, WHEAT, WHITE, WHITESMOKE,
-            YELLOW, YELLOWGREEN);
+// The D65 reference white in XYZ
+static D65_X: float = 0.95047;
+static D65_Y: float = 1.0;
+static D65_Z: float = 1.08883;
 
-    if color.is_none() {
-        return fail_unrecognized(name);
-    }else {
-        return color;
+/** The u'v' chromaticity of the D65 white */
+fn white_uv() -> (float, float) {
+    let d = D65_X + 15.0*D65_Y + 3.0*D65_Z;
+    (4.0*D65_X / d, 9.0*D65_Y / d)
+}
+
+/** Whether chromaticity `p` lies inside the triangle `a` `b` `c`, allowing for rounding */
+fn in_triangle(p : (float, float), a : (float, float), b : (float, float),
+               c : (float, float)) -> bool {
+    let ((px, py), (ax, ay), (bx, by), (cx, cy)) = (p, a, b, c);
+    let det = (by - cy)*(ax - cx) + (cx - bx)*(ay - cy);
+    let l1 = ((by - cy)*(px - cx) + (cx - bx)*(py - cy)) / det;
+    let l2 = ((cy - ay)*(px - cx) + (ax - cx)*(py - cy)) / det;
+    let l3 = 1.0 - l1 - l2;
+    let eps = -1e-4;
+    l1 >= eps && l2 >= eps && l3 >= eps
+}
+
+/** Converts linear-light XYZ to sRGB, clamping out-of-gamut channels */
+fn from_xyz(x : float, y : float, z : float) -> Color {
+    let r = 3.2404542*x - 1.5371385*y - 0.4985314*z;
+    let g = -0.9692660*x + 1.8760108*y + 0.0415560*z;
+    let b = 0.0556434*x - 0.2040259*y + 1.0572252*z;
+    rgb(unit_to_channel(linear_to_srgb(r)), unit_to_channel(linear_to_srgb(g)),
+        unit_to_channel(linear_to_srgb(b)))
+}
+
+/** Converts CIE L*u*v* coordinates (D65 white) to an opaque color */
+pub fn from_luv(l : float, u : float, v : float) -> Color {
+    if l <= 0.0 {
+        return rgb(0, 0, 0);
     }
+    let (un, vn) = white_uv();
+    let up = u / (13.0*l) + un;
+    let vp = v / (13.0*l) + vn;
+    let y = if l > 8.0 {
+        let t = (l + 16.0) / 116.0;
+        D65_Y * t*t*t
+    } else {
+        D65_Y * l * 27.0 / 24389.0
+    };
+    from_xyz(y * 9.0*up / (4.0*vp), y, y * (12.0 - 3.0*up - 20.0*vp) / (4.0*vp))
 }
 
-// Define the colors specified by css
-define_color!(ALICEBLUE, 240, 248, 255)
-define_color!(ANTIQUEWHITE, 250, 235, 215)
-define_color!(AQUA, 0, 255, 255)
-define_color!(AQUAMARINE, 127, 255, 212)
-define_color!(AZURE, 240, 255, 255)
-define_color!(BEIGE, 245, 245, 220)
-define_color!(BISQUE, 255, 228, 196)
-define_color!(BLACK, 0, 0, 0)
-define_color!(BLANCHEDALMOND, 255, 235, 205)
-define_color!(BLUE, 0, 0, 255)
-define_color!(BLUEVIOLET, 138, 43, 226)
-define_color!(BROWN, 165, 42, 42)
+/** Converts L*u*v* polar coordinates (hue in degrees) to an opaque color */
+pub fn from_lchuv(l : float, c : float, h : float) -> Color {
+    let h = h * pi / 180.0;
+    from_luv(l, c * h.cos(), c * h.sin())
+}
+
+/** Converts OKLab coordinates back to sRGB, clamping out-of-gamut channels */
+fn from_oklab(l : float, a : float, b : float, alpha : float) -> Color {
+    let (r, g, b) = oklab_to_linear(l, a, b);
+    rgba(unit_to_channel(linear_to_srgb(r)), unit_to_channel(linear_to_srgb(g)),
+         unit_to_channel(linear_to_srgb(b)), alpha)
+}
+
+/**
+Converts OKLCh coordinates to sRGB. Where the color falls outside the
+gamut, chroma is reduced until it fits instead of clipping channels, so
+lightness and hue are kept.
+*/
+fn from_oklch_in_gamut(l : float, c : float, h : float, alpha : float) -> Color {
+    let (cos_h, sin_h) = ((h * pi / 180.0).cos(), (h * pi / 180.0).sin());
+    let in_gamut = |c : float| {
+        let (r, g, b) = oklab_to_linear(l, c*cos_h, c*sin_h);
+        [r, g, b].iter().all(|v| *v >= -1e-9 && *v <= 1.0 + 1e-9)
+    };
+
+    let mut c = c;
+    if !in_gamut(c) {
+        let mut lo = 0.0;
+        for _ in range(0, 20) {
+            let mid = (lo + c) / 2.0;
+            if in_gamut(mid) { lo = mid } else { c = mid }
+        }
+        c = lo;
+    }
+    from_oklab(l, c*cos_h, c*sin_h, alpha)
+}
+
+/** Linear-light sRGB for OKLab coordinates, unclamped */
+fn oklab_to_linear(l : float, a : float, b : float) -> (float, float, float) {
+    let l_ = l + 0.3963377774*a + 0.2158037573*b;
+    let m_ = l - 0.1055613458*a - 0.0638541728*b;
+    let s_ = l - 0.0894841775*a - 1.2914855480*b;
+    let (l, m, s) = (l_*l_*l_, m_*m_*m_, s_*s_*s_);
+
+    (4.0767416621*l - 3.3077115913*m + 0.2309699292*s,
+     -1.2684380046*l + 2.6097574011*m - 0.3413193965*s,
+     -0.0041960863*l - 0.7034186147*m + 1.7076147010*s)
+}
+
+/** Converts CIE L*a*b* coordinates (D65 white) to an opaque color */
+fn from_lab(l : float, a : float, b : float) -> Color {
+    let fy = (l + 16.0) / 116.0;
+    let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+    let finv = |t : float| {
+        if t*t*t > 216.0 / 24389.0 { t*t*t } else { (116.0*t - 16.0) * 27.0 / 24389.0 }
+    };
+    let y = if l > 8.0 { fy*fy*fy } else { l * 27.0 / 24389.0 };
+    from_xyz(finv(fx) * D65_X, y * D65_Y, finv(fz) * D65_Z)
+}
+
+impl ColorValue {
+    /** Converts back to sRGB, clamping out-of-gamut channels */
+    pub fn to_color(&self) -> Color {
+        let (c0, c1, c2) = self.components;
+        let polar = |h : float| (c1 * (h * pi / 180.0).cos(), c1 * (h * pi / 180.0).sin());
+        let opaque = match self.space {
+            SrgbSpace => rgb(unit_to_channel(c0), unit_to_channel(c1), unit_to_channel(c2)),
+            HslSpace => hsl(c0, c1, c2),
+            HwbSpace => hwba(c0, c1, c2, 1.0),
+            LabSpace => from_lab(c0, c1, c2),
+            LchSpace => {
+                let (a, b) = polar(c2);
+                from_lab(c0, a, b)
+            }
+            OklabSpace => from_oklab(c0, c1, c2, 1.0),
+            OklchSpace => {
+                let (a, b) = polar(c2);
+                from_oklab(c0, a, b, 1.0)
+            }
+        };
+        rgba(opaque.red, opaque.green, opaque.blue, self.alpha)
+    }
+}
+
+/**
+`n` evenly spaced colors from `a` to `b`, both included. Channels are
+interpolated on their gamma-encoded values, or in linear light when
+`linear` is set; alpha is always interpolated directly.
+*/
+pub fn lerp_many(a : Color, b : Color, n : uint, linear : bool) -> ~[Color] {
+    match n {
+        0 => return ~[],
+        1 => return ~[a],
+        _ => ()
+    }
+
+    let lerp = |x : u8, y : u8, t : float| {
+        let (x, y) = (x as float / 255.0, y as float / 255.0);
+        if linear {
+            let (x, y) = (srgb_to_linear(x), srgb_to_linear(y));
+            unit_to_channel(linear_to_srgb(x + (y - x)*t))
+        } else {
+            unit_to_channel(x + (y - x)*t)
+        }
+    };
+
+    vec::from_fn(n, |i| {
+        let t = i as float / (n - 1) as float;
+        rgba(lerp(a.red, b.red, t), lerp(a.green, b.green, t), lerp(a.blue, b.blue, t),
+             a.alpha + (b.alpha - a.alpha)*t)
+    })
+}
+
+/**
+Mixes two colors in HSL space. `weight` 0.0 gives `a` and 1.0 gives `b`.
+Hue travels along the shorter arc of the color wheel when `shorter` is set
+(350 to 10 passes through 0), or the longer one otherwise; saturation,
+lightness and alpha are interpolated linearly.
+*/
+pub fn mix_hsl(a : Color, b : Color, weight : float, shorter : bool) -> Color {
+    let (ha, sa, la) = a.to_hsl();
+    let (hb, sb, lb) = b.to_hsl();
+
+    let mut dh = hb - ha;
+    if shorter {
+        if dh > 180.0 { dh -= 360.0 } else if dh < -180.0 { dh += 360.0 }
+    } else {
+        if dh > 0.0 && dh < 180.0 { dh -= 360.0 } else if dh < 0.0 && dh > -180.0 { dh += 360.0 }
+    }
+
+    let h = (ha + dh*weight) % 360.0;
+    hsla(if h < 0.0 { h + 360.0 } else { h },
+         sa + (sb - sa)*weight,
+         la + (lb - la)*weight,
+         a.alpha + (b.alpha - a.alpha)*weight)
+}
+
+/**
+Shifts the OKLab lightness of every color in a palette by the same amount
+so that the mean lightness becomes `target_mean_l` (0.0..1.0). Each result
+is clamped on its own, so relative relationships survive unless a color
+runs into black or white. Alpha is kept.
+*/
+pub fn normalize_lightness(colors : &[Color], target_mean_l : float) -> ~[Color] {
+    if colors.is_empty() {
+        return ~[];
+    }
+    let labs = colors.map(|c| c.to_oklab());
+    let mean = labs.iter().fold(0.0, |sum, &(l, _, _)| sum + l) / labs.len() as float;
+    let shift = target_mean_l - mean;
+    vec::from_fn(colors.len(), |i| {
+        let (l, a, b) = labs[i];
+        let l = l + shift;
+        from_oklab(if l < 0.0 { 0.0 } else if l > 1.0 { 1.0 } else { l }, a, b,
+                   colors[i].alpha)
+    })
+}
+
+/**
+The circular mean of the HSL hues of `colors`, in degrees, weighted by
+saturation so grays do not pull it around. The mean of 350 and 10 is 0,
+not 180. Returns None if there is no hue to average.
+*/
+pub fn average_hue(colors : &[Color]) -> Option<float> {
+    let (mut x, mut y) = (0.0, 0.0);
+    for c in colors.iter() {
+        let (h, s, _) = c.to_hsl();
+        let h = h * pi / 180.0;
+        x += s * h.cos();
+        y += s * h.sin();
+    }
+    if (x*x + y*y).sqrt() < 1e-9 {
+        return None;
+    }
+    let h = y.atan2(&x) * 180.0 / pi;
+    Some(if h < 0.0 { h + 360.0 } else { h })
+}
+
+/**
+APCA lightness contrast (Lc) of `text` drawn on `bg`, using the constants of
+APCA 0.0.98G-4g. Unlike the WCAG 2 ratio this is directional: dark text on a
+light background is positive (up to about 106), light text on a dark
+background is negative (down to about -108), and very low contrasts clip to 0.
+*/
+pub fn apca_contrast(text : &Color, bg : &Color) -> float {
+    fn screen_luminance(c : &Color) -> float {
+        let y = 0.2126729*(c.red as float / 255.0).pow(&2.4) +
+                0.7151522*(c.green as float / 255.0).pow(&2.4) +
+                0.0721750*(c.blue as float / 255.0).pow(&2.4);
+        // Soft clamp near black
+        if y < 0.022 { y + (0.022 - y).pow(&1.414) } else { y }
+    }
+
+    let y_text = screen_luminance(text);
+    let y_bg = screen_luminance(bg);
+    if (y_bg - y_text).abs() < 0.0005 {
+        return 0.0;
+    }
+
+    if y_bg > y_text {
+        let sapc = (y_bg.pow(&0.56) - y_text.pow(&0.57)) * 1.14;
+        if sapc < 0.1 { 0.0 } else { (sapc - 0.027) * 100.0 }
+    } else {
+        let sapc = (y_bg.pow(&0.65) - y_text.pow(&0.62)) * 1.14;
+        if sapc > -0.1 { 0.0 } else { (sapc + 0.027) * 100.0 }
+    }
+}
+
+/**
+Converts a sample of the RGB cube to HSL and back through `hsl`, every
+`step` values along each axis (plus 255), and returns the first color that
+does not come back within one unit per channel along with what it came
+back as. Downstream crates can run this against their own step size.
+*/
+pub fn check_hsl_round_trip(step : uint) -> Option<(Color, Color)> {
+    assert!(step > 0);
+    let mut values = ~[];
+    let mut v = 0u;
+    while v < 255 {
+        values.push(v as u8);
+        v += step;
+    }
+    values.push(255u8);
+
+    for &r in values.iter() {
+        for &g in values.iter() {
+            for &b in values.iter() {
+                let c = rgb(r, g, b);
+                let (h, s, l) = c.to_hsl();
+                let back = hsl(h, s, l);
+                if !same_after_rounding(&c, &back) {
+                    return Some((c, back));
+                }
+            }
+        }
+    }
+    None
+}
+
+/**
+Whether `a` and `b` could be the same color before rounding to bytes: every
+channel within 1 and alpha within 1/255. Use this rather than exact equality
+to compare colors produced by hsl() or other float conversions.
+*/
+pub fn same_after_rounding(a : &Color, b : &Color) -> bool {
+    a.approx_eq(b, 1, 1.0 / 255.0)
+}
+
+/** Total OKLab distance travelled by a gradient through `stops` */
+pub fn perceptual_length(stops : &[Color]) -> float {
+    let mut length = 0.0;
+    for i in range(1u, stops.len()) {
+        length += stops[i - 1].delta_e(&stops[i]);
+    }
+    length
+}
+
+/**
+Where two palettes differ by more than `tol` (OKLab `delta_e`), as pairs
+of index and difference. Indices past the end of the shorter palette are
+always reported, with an infinite difference.
+*/
+pub fn diff_palettes(a : &[Color], b : &[Color], tol : float) -> ~[(uint, float)] {
+    let mut diffs = ~[];
+    for i in range(0u, num::max(a.len(), b.len())) {
+        let d = if i < a.len() && i < b.len() { a[i].delta_e(&b[i]) } else { float::infinity };
+        if d > tol {
+            diffs.push((i, d));
+        }
+    }
+    diffs
+}
+
+/**
+Positions in 0.0..1.0 for each of `stops` such that every segment of the
+gradient covers the same perceptual distance per unit length, which avoids
+visible banding where one segment changes much faster than another
+*/
+pub fn perceptual_positions(stops : &[Color]) -> ~[float] {
+    let total = perceptual_length(stops);
+    let mut positions = ~[];
+    let mut travelled = 0.0;
+    for i in range(0u, stops.len()) {
+        if i > 0 {
+            travelled += stops[i - 1].delta_e(&stops[i]);
+        }
+        positions.push(if total > 0.0 {
+            travelled / total
+        } else if stops.len() > 1 {
+            i as float / (stops.len() - 1) as float
+        } else {
+            0.0
+        });
+    }
+    positions
+}
+
+/**
+Writes the relative luminance of each of `pixels` into the matching slot of
+`out`, so a caller scanning an image can reuse one buffer for every row
+*/
+pub fn luminance_row(pixels : &[Color], out : &mut [float]) -> Result<(), ~str> {
+    if pixels.len() != out.len() {
+        return Err(fmt!("luminance_row: %u pixels but room for %u values",
+                        pixels.len(), out.len()));
+    }
+    for i in range(0u, pixels.len()) {
+        out[i] = pixels[i].luminance();
+    }
+    Ok(())
+}
+
+/**
+Replaces every RGBA pixel in `buf` with the nearest color of `palette`,
+in place. This is the one-call path for exporting indexed color.
+*/
+pub fn apply_palette_rgba(buf : &mut [u8], palette : &[Color]) -> Result<(), ~str> {
+    if buf.len() % 4 != 0 {
+        return Err(fmt!("apply_palette_rgba: buffer length %u is not a multiple of 4",
+                        buf.len()));
+    }
+    if palette.is_empty() {
+        return Err(~"apply_palette_rgba: empty palette");
+    }
+    for i in range(0u, buf.len() / 4) {
+        let p = i * 4;
+        let pixel = rgba(buf[p], buf[p + 1], buf[p + 2], buf[p + 3] as float / 255.0);
+        let nearest = pixel.map_to_palette(palette);
+        buf[p] = nearest.red;
+        buf[p + 1] = nearest.green;
+        buf[p + 2] = nearest.blue;
+        buf[p + 3] = alpha_to_byte(nearest.alpha);
+    }
+    Ok(())
+}
+
+/**
+Like `apply_palette_rgba`, but diffuses each pixel's quantization error to
+its unvisited neighbours (Floyd-Steinberg), so smooth gradients come out as
+a mix of palette colors instead of hard bands. Alpha is left untouched.
+*/
+pub fn dither_rgba(buf : &mut [u8], width : uint, height : uint,
+                   palette : &[Color]) -> Result<(), ~str> {
+    if buf.len() != width * height * 4 {
+        return Err(fmt!("dither_rgba: buffer length %u does not match %ux%u pixels",
+                        buf.len(), width, height));
+    }
+    if palette.is_empty() {
+        return Err(~"dither_rgba: empty palette");
+    }
+
+    let mut error = vec::from_elem(width * height * 3, 0.0f);
+    for y in range(0u, height) {
+        for x in range(0u, width) {
+            let i = y * width + x;
+            let mut wanted = [0.0f, 0.0, 0.0];
+            for c in range(0u, 3) {
+                let v = buf[i*4 + c] as float + error[i*3 + c];
+                wanted[c] = if v < 0.0 { 0.0 } else if v > 255.0 { 255.0 } else { v };
+            }
+
+            let pixel = rgb(round_uint(wanted[0]) as u8, round_uint(wanted[1]) as u8,
+                            round_uint(wanted[2]) as u8);
+            let chosen = pixel.map_to_palette(palette);
+            let chosen = [chosen.red, chosen.green, chosen.blue];
+
+            for c in range(0u, 3) {
+                buf[i*4 + c] = chosen[c];
+                let e = wanted[c] - chosen[c] as float;
+                if x + 1 < width {
+                    error[(i + 1)*3 + c] += e * 7.0 / 16.0;
+                }
+                if y + 1 < height {
+                    let below = i + width;
+                    if x > 0 {
+                        error[(below - 1)*3 + c] += e * 3.0 / 16.0;
+                    }
+                    error[below*3 + c] += e * 5.0 / 16.0;
+                    if x + 1 < width {
+                        error[(below + 1)*3 + c] += e * 1.0 / 16.0;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+Decodes a pair of ASCII hex digits, either case, into a byte. This is the
+single place hex notation is decoded, so every parser agrees on it.
+*/
+pub fn decode_hex_byte(hi : u8, lo : u8) -> Option<u8> {
+    fn nibble(c : u8) -> Option<u8> {
+        match c as char {
+            '0'..'9' => Some(c - '0' as u8),
+            'a'..'f' => Some(c - 'a' as u8 + 10),
+            'A'..'F' => Some(c - 'A' as u8 + 10),
+            _ => None
+        }
+    }
+
+    match (nibble(hi), nibble(lo)) {
+        (Some(h), Some(l)) => Some(h << 4 | l),
+        _ => None
+    }
+}
+
+/** Rounds a non-negative float to the nearest integer */
+fn round_uint(v : float) -> uint {
+    (v as c_double).round() as uint
+}
+
+/** Expands a gamma-encoded sRGB channel in 0.0..1.0 to linear light */
+fn srgb_to_linear(c : float) -> float {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).pow(&2.4) }
+}
+
+/** Encodes a linear-light channel in 0.0..1.0 with the sRGB transfer curve */
+fn linear_to_srgb(c : float) -> float {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.pow(&(1.0 / 2.4)) - 0.055 }
+}
+
+/** Scales a 0.0..1.0 alpha to a byte, rounding to nearest */
+pub fn alpha_to_byte(a : float) -> u8 {
+    (255.0*a as c_double).round() as u8
+}
+
+pub mod parsing {
+    use std::num;
+    use std::str;
+    use std::float::consts::pi;
+    use super::*;
+    use super::{Color, rgb, rgba, hsl, hsla, hsv, hsva, hwba, unit_to_channel, finite};
+
+    /**
+    Parses an rgb channel, either a 0..255 integer or a percentage of 255.
+    Out of range values are clamped, as CSS requires.
+    */
+    fn parse_channel(value : &str) -> Option<u8> {
+        if value.ends_with("%") {
+            let percent : Option<float> = FromStr::from_str(value.slice_to(value.len() - 1));
+            match percent {
+                Some(p) if p == p => Some(unit_to_channel(p / 100.0)),
+                _ => None
+            }
+        } else {
+            let channel : Option<int> = FromStr::from_str(value);
+            channel.map(|c| num::max(0, num::min(*c, 255)) as u8)
+        }
+    }
+
+    /**
+    The trimmed, comma-separated arguments of a function such as rgb(...)
+    whose name and paren take up the first `open` bytes. Empty if the input
+    is cut short or lacks the closing paren.
+    */
+    fn function_args<'a>(color : &'a str, open : uint) -> ~[&'a str] {
+        if color.len() <= open || !color.ends_with(")") {
+            return ~[];
+        }
+        color.slice(open, color.len() - 1).split_iter(',').map(|s| s.trim()).collect()
+    }
+
+    /**
+    The arguments of a function in the CSS Color 4 form, rgb(255 0 0 / 50%),
+    where components are separated by spaces and alpha follows a slash: the
+    components, and the alpha token if there is one. None for the legacy
+    comma-separated form.
+    */
+    fn modern_args<'a>(color : &'a str, open : uint) -> Option<(~[&'a str], Option<&'a str>)> {
+        if color.len() <= open || !color.ends_with(")") {
+            return None;
+        }
+        let inner = color.slice(open, color.len() - 1);
+        if inner.contains_char(',') {
+            return None;
+        }
+        let (components, alpha) = match inner.find('/') {
+            Some(i) => (inner.slice_to(i), Some(inner.slice_from(i + 1).trim())),
+            None => (inner, None)
+        };
+        Some((components.word_iter().collect(), alpha))
+    }
+
+    /**
+    Parses the alpha after the slash in the CSS Color 4 form, a number or a
+    percentage. Anything else is reported rather than defaulting to opaque.
+    */
+    fn parse_modern_alpha(color : &str, alpha : Option<&str>) -> Result<float, ColorParseError> {
+        match alpha {
+            None => Ok(1.0),
+            Some(token) => match parse_fraction(token) {
+                Some(a) if !finite([a]) => { fail_unrecognized(color); Err(OutOfRange) }
+                Some(a) => Ok(a),
+                None => { fail_unrecognized(color); Err(BadChannelValue(token.to_owned())) }
+            }
+        }
+    }
+
+    /** Parses rgb(r g b) or rgb(r g b / a); rgba() accepts the same */
+    fn parse_modern_rgb(color : &str, cols : &[&str], alpha : Option<&str>)
+                        -> Result<Color, ColorParseError> {
+        if cols.len() != 3u { return reject(color, BadComponentCount); }
+
+        let a = match parse_modern_alpha(color, alpha) {
+            Ok(a) => a,
+            Err(e) => return Err(e)
+        };
+        match (parse_channel(cols[0]), parse_channel(cols[1]), parse_channel(cols[2])) {
+          (Some(r), Some(g), Some(b)) => { Ok(rgba(r, g, b, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form rgb(foo,bar,baz) */
+    fn parse_rgb(color : &str) -> Result<Color, ColorParseError> {
+        match modern_args(color, 4u) {
+            Some((cols, alpha)) => return parse_modern_rgb(color, cols, alpha),
+            None => ()
+        }
+        let cols = function_args(color, 4u);
+
+        if cols.len() != 3u { return reject(color, BadComponentCount); }
+
+        match (parse_channel(cols[0]), parse_channel(cols[1]), 
+               parse_channel(cols[2])) {
+          (Some(r), Some(g), Some(b)) => { Ok(rgb(r, g, b)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form #rrggbb, #rgb, #rrggbbaa or #rgba */
+    fn parse_hex(color : &str) -> Result<Color, ColorParseError> {
+        HexParser::new().parse_result(color)
+    }
+
+    /**
+    Configurable hex color parsing. The defaults follow CSS: a leading `#`
+    is required and alpha, if present, comes last (#rrggbbaa). Platforms
+    such as Android write alpha first (#aarrggbb) and often drop the `#`.
+
+        let argb = HexParser::new().alpha_first(true).require_hash(false);
+    */
+    pub struct HexParser {
+        priv alpha_first: bool,
+        priv require_hash: bool,
+    }
+
+    impl HexParser {
+        pub fn new() -> HexParser {
+            HexParser { alpha_first: false, require_hash: true }
+        }
+
+        /** Read the alpha of 4 and 8 digit forms from the front instead of the back */
+        pub fn alpha_first(self, alpha_first : bool) -> HexParser {
+            HexParser { alpha_first: alpha_first, .. self }
+        }
+
+        /** Whether a leading `#` is mandatory; it is always allowed */
+        pub fn require_hash(self, require_hash : bool) -> HexParser {
+            HexParser { require_hash: require_hash, .. self }
+        }
+
+        pub fn parse(&self, color : &str) -> Option<Color> {
+            match self.parse_result(color) {
+                Ok(c) => Some(c),
+                Err(_) => None
+            }
+        }
+
+        fn parse_result(&self, color : &str) -> Result<Color, ColorParseError> {
+            let digits = if color.starts_with("#") {
+                color.slice_from(1).as_bytes()
+            } else if !self.require_hash {
+                color.as_bytes()
+            } else {
+                return reject(color, WrongNotation);
+            };
+
+            // Expand the short forms so every case below has two digits per channel
+            let mut full = ~[];
+            match digits.len() {
+                3 | 4 => for d in digits.iter() { full.push(*d); full.push(*d); },
+                6 | 8 => full.push_all(digits),
+                _ => return reject(color, BadComponentCount)
+            }
+            if full.len() == 6 {
+                full.push_all(bytes!("ff"));
+            } else if self.alpha_first {
+                // Rotate aarrggbb into rrggbbaa
+                let alpha = full.slice(0, 2).to_owned();
+                full = full.slice_from(2).to_owned() + alpha;
+            }
+
+            match (decode_hex_byte(full[0], full[1]),
+                   decode_hex_byte(full[2], full[3]),
+                   decode_hex_byte(full[4], full[5]),
+                   decode_hex_byte(full[6], full[7])) {
+              (Some(r), Some(g), Some(b), Some(a)) => { Ok(rgba(r, g, b, a as float / 255.0)) }
+              _ => { reject(color, BadNumber) }
+            }
+        }
+    }
+
+    /**
+    Parses an alpha value. Lenient mode also accepts hand-written
+    fractions such as 1/2 and constant calc() expressions.
+    */
+    fn parse_alpha(alpha : &str, lenient : bool) -> Option<float> {
+        if lenient {
+            if alpha.starts_with("calc(") && alpha.ends_with(")") {
+                return eval_calc(alpha.slice(5u, alpha.len() - 1));
+            }
+            match alpha.find('/') {
+                Some(i) => {
+                    let num : Option<float> = FromStr::from_str(alpha.slice_to(i).trim());
+                    let den : Option<float> = FromStr::from_str(alpha.slice_from(i + 1).trim());
+                    return match (num, den) {
+                        (Some(n), Some(d)) if d != 0.0 => Some(n / d),
+                        _ => None
+                    };
+                }
+                None => ()
+            }
+        }
+        FromStr::from_str(alpha)
+    }
+
+    /**
+    Folds a calc() body made only of numbers, percentages, + - * / and
+    parentheses. Anything else, including units, var() and division by
+    zero, is rejected.
+    */
+    fn eval_calc(expr : &str) -> Option<float> {
+        let bytes = expr.as_bytes();
+        let mut pos = 0u;
+        let value = calc_sum(bytes, &mut pos);
+        skip_spaces(bytes, &mut pos);
+        if pos == bytes.len() { value } else { None }
+    }
+
+    fn skip_spaces(bytes : &[u8], pos : &mut uint) {
+        while *pos < bytes.len() && bytes[*pos] == ' ' as u8 {
+            *pos += 1;
+        }
+    }
+
+    fn calc_sum(bytes : &[u8], pos : &mut uint) -> Option<float> {
+        let mut acc = match calc_product(bytes, pos) { Some(v) => v, None => return None };
+        loop {
+            skip_spaces(bytes, pos);
+            if *pos >= bytes.len() { return Some(acc); }
+            let op = bytes[*pos] as char;
+            if op != '+' && op != '-' { return Some(acc); }
+            *pos += 1;
+            match calc_product(bytes, pos) {
+                Some(v) => acc = if op == '+' { acc + v } else { acc - v },
+                None => return None
+            }
+        }
+    }
+
+    fn calc_product(bytes : &[u8], pos : &mut uint) -> Option<float> {
+        let mut acc = match calc_factor(bytes, pos) { Some(v) => v, None => return None };
+        loop {
+            skip_spaces(bytes, pos);
+            if *pos >= bytes.len() { return Some(acc); }
+            let op = bytes[*pos] as char;
+            if op != '*' && op != '/' { return Some(acc); }
+            *pos += 1;
+            match calc_factor(bytes, pos) {
+                Some(v) if op == '*' => acc *= v,
+                Some(v) if v != 0.0 => acc /= v,
+                _ => return None
+            }
+        }
+    }
+
+    fn calc_factor(bytes : &[u8], pos : &mut uint) -> Option<float> {
+        skip_spaces(bytes, pos);
+        if *pos >= bytes.len() { return None; }
+        match bytes[*pos] as char {
+            '(' => {
+                *pos += 1;
+                let value = calc_sum(bytes, pos);
+                skip_spaces(bytes, pos);
+                if *pos < bytes.len() && bytes[*pos] == ')' as u8 {
+                    *pos += 1;
+                    value
+                } else {
+                    None
+                }
+            }
+            '-' => {
+                *pos += 1;
+                calc_factor(bytes, pos).map(|v| -*v)
+            }
+            _ => {
+                let start = *pos;
+                while *pos < bytes.len() &&
+                      (bytes[*pos] == '.' as u8 || (bytes[*pos] as char).is_digit()) {
+                    *pos += 1;
+                }
+                let number : Option<float> =
+                    FromStr::from_str(str::from_bytes(bytes.slice(start, *pos)));
+                if *pos < bytes.len() && bytes[*pos] == '%' as u8 {
+                    *pos += 1;
+                    number.map(|v| *v / 100.0)
+                } else {
+                    number
+                }
+            }
+        }
+    }
+
+    /** Parses a color specification in the form rgba(foo,bar,baz,qux) */
+    fn parse_rgba(color : &str, lenient : bool) -> Result<Color, ColorParseError> {
+        match modern_args(color, 5u) {
+            Some((cols, alpha)) => return parse_modern_rgb(color, cols, alpha),
+            None => ()
+        }
+        let cols = function_args(color, 5u);
+
+        if cols.len() != 4u { return reject(color, BadComponentCount); }
+
+        match (parse_channel(cols[0]), parse_channel(cols[1]), 
+               parse_channel(cols[2]), parse_alpha(cols[3], lenient)) {
+          (Some(_), Some(_), Some(_), Some(a)) if !finite([a]) => { reject(color, OutOfRange) }
+          (Some(r), Some(g), Some(b), Some(a)) => { Ok(rgba(r, g, b, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /**
+    Parses an hsl hue in degrees, or in any CSS angle unit. Whole degrees
+    up to 360 are by far the most common, so they skip float parsing;
+    everything else takes the float path.
+    */
+    fn parse_hue(hue : &str) -> Option<float> {
+        // grad must be tried before rad, which it ends with
+        let units = [("deg", 1.0), ("grad", 0.9), ("rad", 180.0 / pi), ("turn", 360.0)];
+        for &(unit, degrees) in units.iter() {
+            if hue.ends_with(unit) {
+                let value : Option<float> = FromStr::from_str(hue.slice_to(hue.len() - unit.len()));
+                return value.map(|v| *v * degrees);
+            }
+        }
+
+        if hue.len() > 0 && hue.len() <= 3 &&
+           hue.byte_iter().all(|b| b >= '0' as u8 && b <= '9' as u8) {
+            let degrees : Option<uint> = FromStr::from_str(hue);
+            match degrees {
+                Some(d) if d <= 360 => return Some(d as float),
+                _ => ()
+            }
+        }
+        FromStr::from_str(hue)
+    }
+
+    /** Parses a 0.0..1.0 value written either as a plain number or as a percentage */
+    fn parse_fraction(value : &str) -> Option<float> {
+        if value.ends_with("%") {
+            let percent : Option<float> = FromStr::from_str(value.slice_to(value.len() - 1));
+            percent.map(|p| *p / 100.0)
+        } else {
+            FromStr::from_str(value)
+        }
+    }
+
+    /** Parses the space-separated `h s l` or `h s l / a` of CSS Color 4 */
+    fn parse_modern_hsl(color : &str, vals : &[&str], alpha : Option<&str>)
+                        -> Result<Color, ColorParseError> {
+        if vals.len() != 3u { return reject(color, BadComponentCount); }
+
+        let a = match parse_modern_alpha(color, alpha) {
+            Ok(a) => a,
+            Err(e) => return Err(e)
+        };
+        match (parse_hue(vals[0]), parse_fraction(vals[1]), parse_fraction(vals[2])) {
+          (Some(h), Some(s), Some(l)) if !finite([h, s, l]) => { reject(color, OutOfRange) }
+          (Some(h), Some(s), Some(l)) => { Ok(hsla(h, s, l, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form hsl(foo,bar,baz) */
+    fn parse_hsl(color : &str) -> Result<Color, ColorParseError> {
+        match modern_args(color, 4u) {
+            Some((vals, alpha)) => return parse_modern_hsl(color, vals, alpha),
+            None => ()
+        }
+        let vals = function_args(color, 4u);
+
+        if vals.len() != 3u { return reject(color, BadComponentCount); }
+
+        match (parse_hue(vals[0]), parse_fraction(vals[1]), 
+               parse_fraction(vals[2])) {
+          (Some(h), Some(s), Some(l)) if !finite([h, s, l]) => { reject(color, OutOfRange) }
+          (Some(h), Some(s), Some(l)) => { Ok(hsl(h, s, l)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form hsla(foo,bar,baz,qux) */
+    fn parse_hsla(color : &str) -> Result<Color, ColorParseError> {
+        match modern_args(color, 5u) {
+            Some((vals, alpha)) => return parse_modern_hsl(color, vals, alpha),
+            None => ()
+        }
+        let vals = function_args(color, 5u);
+
+        if vals.len() != 4u { return reject(color, BadComponentCount); }
+
+        match (parse_hue(vals[0]), parse_fraction(vals[1]), 
+               parse_fraction(vals[2]), FromStr::from_str(vals[3])) {
+          (Some(h), Some(s), Some(l), Some(a)) if !finite([h, s, l, a]) => {
+            reject(color, OutOfRange)
+          }
+          (Some(h), Some(s), Some(l), Some(a)) => { Ok(hsla(h, s, l, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form hsv(foo,bar,baz) */
+    fn parse_hsv(color : &str) -> Result<Color, ColorParseError> {
+        let vals = function_args(color, 4u);
+
+        if vals.len() != 3u { return reject(color, BadComponentCount); }
+
+        match (parse_hue(vals[0]), parse_fraction(vals[1]),
+               parse_fraction(vals[2])) {
+          (Some(h), Some(s), Some(v)) if !finite([h, s, v]) => { reject(color, OutOfRange) }
+          (Some(h), Some(s), Some(v)) => { Ok(hsv(h, s, v)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /** Parses a color specification in the form hsva(foo,bar,baz,qux) */
+    fn parse_hsva(color : &str) -> Result<Color, ColorParseError> {
+        let vals = function_args(color, 5u);
+
+        if vals.len() != 4u { return reject(color, BadComponentCount); }
+
+        match (parse_hue(vals[0]), parse_fraction(vals[1]),
+               parse_fraction(vals[2]), FromStr::from_str(vals[3])) {
+          (Some(h), Some(s), Some(v), Some(a)) if !finite([h, s, v, a]) => {
+            reject(color, OutOfRange)
+          }
+          (Some(h), Some(s), Some(v), Some(a)) => { Ok(hsva(h, s, v, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    /**
+    Parses a color specification in the form hwb(h w b) or hwb(h w b / a),
+    or the comma-separated hwb(h,w,b) some older tools write
+    */
+    fn parse_hwb(color : &str) -> Result<Color, ColorParseError> {
+        let (vals, alpha) = match modern_args(color, 4u) {
+            Some((vals, alpha)) => (vals, alpha),
+            None => (function_args(color, 4u), None)
+        };
+
+        if vals.len() != 3u { return reject(color, BadComponentCount); }
+
+        let a = match parse_modern_alpha(color, alpha) {
+            Ok(a) => a,
+            Err(e) => return Err(e)
+        };
+        match (parse_hue(vals[0]), parse_fraction(vals[1]), parse_fraction(vals[2])) {
+          (Some(h), Some(w), Some(b)) if !finite([h, w, b]) => { reject(color, OutOfRange) }
+          (Some(h), Some(w), Some(b)) => { Ok(hwba(h, w, b, a)) }
+          _ => { reject(color, BadNumber) }
+        }
+    }
+
+    // Currently colors are supported in rgb(a,b,c) form and also by
+    // keywords for several common colors.
+    // TODO: extend this
+    pub fn parse_color(color : &str) -> Option<Color> {
+        match parse_color_mode(color, false) {
+            Ok(c) => Some(c),
+            Err(_) => None
+        }
+    }
+
+    /** Like `parse_color`, but says why a color was rejected */
+    pub fn parse_color_result(color : &str) -> Result<Color, ColorParseError> {
+        parse_color_mode(color, false)
+    }
+
+    /**
+    Parses a color straight from raw stylesheet bytes. Color syntax is pure
+    ASCII, so anything else is rejected up front and the bytes never need
+    UTF-8 validation.
+    */
+    pub fn parse_color_bytes(color : &[u8]) -> Option<Color> {
+        if !color.iter().all(|b| *b < 0x80) {
+            return None;
+        }
+        // ASCII is valid UTF-8
+        parse_color(str::from_bytes_slice(color))
+    }
+
+    /**
+    Like `parse_color`, but also accepts some invalid notations that turn
+    up in hand-written stylesheets, e.g. a fractional alpha in rgba(0,0,0,1/2)
+    or an IE hack character in front of the value, as in `*red`
+    */
+    pub fn parse_color_lenient(color : &str) -> Option<Color> {
+        match parse_color_mode(color, true) {
+            Ok(c) => Some(c),
+            Err(_) => None
+        }
+    }
+
+    /**
+    Tries `parse_color` first and, if that fails, repairs the kinds of damage
+    common in copy-pasted or machine-generated values. The repairs are
+    applied cumulatively in this order, and the first result that parses
+    with `parse_color_lenient` wins:
+
+    1. url-decoding, `%23ff0000`
+    2. stripping a pair of surrounding quotes, `"red"`
+    3. dropping whitespace before the paren, `rgb (1,2,3)`
+    4. dropping hyphens from keywords, `light-blue`
+    5. dropping units from components, `rgb(255px,0,0)`
+    */
+    pub fn parse_color_best_effort(color : &str) -> Option<Color> {
+        match parse_color(color) {
+            Some(c) => return Some(c),
+            None => ()
+        }
+        let mut repaired = color.trim().to_owned();
+        for step in range(0u, 5) {
+            repaired = match step {
+                0 => url_decode(repaired),
+                1 => strip_quotes(repaired),
+                2 => squash_paren_space(repaired),
+                3 => dehyphenate_keyword(repaired),
+                _ => strip_component_units(repaired)
+            };
+            match parse_color_lenient(repaired) {
+                Some(c) => return Some(c),
+                None => ()
+            }
+        }
+        None
+    }
+
+    /** Decodes %XX escapes that stand for ASCII characters */
+    fn url_decode(s : &str) -> ~str {
+        let bytes = s.as_bytes();
+        let mut out = ~[];
+        let mut i = 0u;
+        while i < bytes.len() {
+            let decoded = if bytes[i] == '%' as u8 && i + 2 < bytes.len() {
+                decode_hex_byte(bytes[i + 1], bytes[i + 2])
+            } else {
+                None
+            };
+            match decoded {
+                Some(b) if b < 0x80 => { out.push(b); i += 3; }
+                _ => { out.push(bytes[i]); i += 1; }
+            }
+        }
+        str::from_bytes(out)
+    }
+
+    fn strip_quotes(s : &str) -> ~str {
+        let s = s.trim();
+        if s.len() >= 2 && ((s.starts_with("\"") && s.ends_with("\"")) ||
+                            (s.starts_with("'") && s.ends_with("'"))) {
+            s.slice(1, s.len() - 1).trim().to_owned()
+        } else {
+            s.to_owned()
+        }
+    }
+
+    fn squash_paren_space(s : &str) -> ~str {
+        match s.find('(') {
+            Some(i) => s.slice_to(i).trim_right().to_owned() + s.slice_from(i),
+            None => s.to_owned()
+        }
+    }
+
+    fn dehyphenate_keyword(s : &str) -> ~str {
+        if s.starts_with("#") || s.contains_char('(') {
+            s.to_owned()
+        } else {
+            s.replace("-", "")
+        }
+    }
+
+    fn strip_component_units(s : &str) -> ~str {
+        let (open, close) = match (s.find('('), s.rfind(')')) {
+            (Some(o), Some(c)) if o < c => (o, c),
+            _ => return s.to_owned()
+        };
+        let mut out = s.slice_to(open + 1).to_owned();
+        for (i, arg) in s.slice(open + 1, close).split_iter(',').enumerate() {
+            if i > 0 { out.push_char(','); }
+            let arg = arg.trim();
+            let mut end = arg.len();
+            while end > 0 && ((arg[end - 1] >= 'a' as u8 && arg[end - 1] <= 'z' as u8) ||
+                              (arg[end - 1] >= 'A' as u8 && arg[end - 1] <= 'Z' as u8)) {
+                end -= 1;
+            }
+            out.push_str(arg.slice_to(end));
+        }
+        out.push_str(s.slice_from(close));
+        out
+    }
+
+    /** The notation a color was written in */
+    #[deriving(Eq)]
+    pub enum ColorSyntax {
+        RgbSyntax,
+        RgbaSyntax,
+        HslSyntax,
+        HslaSyntax,
+        HsvSyntax,
+        HsvaSyntax,
+        HwbSyntax,
+        HexSyntax,
+        /// The canonical keyword, and the alias actually written if it differs
+        KeywordSyntax(~str, Option<~str>),
+    }
+
+    /** Why a string failed to parse as a color */
+    #[deriving(Eq)]
+    pub enum ColorParseError {
+        /// Not a color keyword, nor any other notation
+        UnknownKeyword,
+        /// The input is not written in the notation that was tried
+        WrongNotation,
+        /// The wrong number of components for the notation
+        BadComponentCount,
+        /// A component that is not a valid number
+        BadNumber,
+        /// A number, such as NaN or infinity, that no color can have
+        OutOfRange,
+        /// A component that is not even the right kind of value, e.g. a keyword
+        BadChannelValue(~str),
+    }
+
+    /** Logs an unparseable color and fails with `err` */
+    fn reject(color : &str, err : ColorParseError) -> Result<Color, ColorParseError> {
+        fail_unrecognized(color);
+        Err(err)
+    }
+
+    /**
+    Like `parse_color`, but also reports the notation the color was written
+    in. Keyword aliases such as `grey` or `cyan` are reported with their
+    canonical spelling (`gray`, `aqua`) so a formatter can normalize them.
+    */
+    pub fn parse_color_tagged(color : &str) -> Option<(Color, ColorSyntax)> {
+        let parsed = match parse_color(color) {
+            Some(c) => c,
+            None => return None
+        };
+        let syntax = match color.trim() {
+            c if c.starts_with("rgba(") => RgbaSyntax,
+            c if c.starts_with("rgb(") => RgbSyntax,
+            c if c.starts_with("hsla(") => HslaSyntax,
+            c if c.starts_with("hsl(") => HslSyntax,
+            c if c.starts_with("hsva(") => HsvaSyntax,
+            c if c.starts_with("hwb(") => HwbSyntax,
+            c if c.starts_with("hsv(") => HsvSyntax,
+            c if c.starts_with("#") => HexSyntax,
+            c => keyword_syntax(c)
+        };
+        Some((parsed, syntax))
+    }
+
+    fn keyword_syntax(name : &str) -> ColorSyntax {
+        let name = name.trim().to_owned().into_ascii().to_lower().into_str();
+        match canonical_keyword(name) {
+            Some(canonical) => KeywordSyntax(canonical.to_owned(), Some(name)),
+            None => KeywordSyntax(name, None)
+        }
+    }
+
+    /**
+    Runs every notation's parser against `color` and reports what each one
+    made of it. Each function notation comes before its alpha variant, then
+    hex, then keywords; this isn't the order `parse_color` dispatches in,
+    which checks longer prefixes first. Meant for tooling that explains why
+    a string parsed the way it did.
+    */
+    pub fn explain(color : &str) -> ~[(ColorSyntax, Result<Color, ColorParseError>)] {
+        let color = color.trim();
+        let mut attempts = ~[];
+        attempts.push((RgbSyntax, if color.starts_with("rgb(") {
+            parse_rgb(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((RgbaSyntax, if color.starts_with("rgba(") {
+            parse_rgba(color, false)
+        } else { Err(WrongNotation) }));
+        attempts.push((HslSyntax, if color.starts_with("hsl(") {
+            parse_hsl(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((HslaSyntax, if color.starts_with("hsla(") {
+            parse_hsla(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((HsvSyntax, if color.starts_with("hsv(") {
+            parse_hsv(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((HsvaSyntax, if color.starts_with("hsva(") {
+            parse_hsva(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((HwbSyntax, if color.starts_with("hwb(") {
+            parse_hwb(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((HexSyntax, if color.starts_with("#") {
+            parse_hex(color)
+        } else { Err(WrongNotation) }));
+        attempts.push((keyword_syntax(color), parse_keyword(color)));
+        attempts
+    }
+
+    /** The preferred spelling of a keyword that has synonyms */
+    fn canonical_keyword(name : &str) -> Option<&'static str> {
+        match name {
+            "grey" => Some("gray"),
+            "darkgrey" => Some("darkgray"),
+            "darkslategrey" => Some("darkslategray"),
+            "dimgrey" => Some("dimgray"),
+            "lightgrey" => Some("lightgray"),
+            "lightslategrey" => Some("lightslategray"),
+            "slategrey" => Some("slategray"),
+            "cyan" => Some("aqua"),
+            "magenta" => Some("fuchsia"),
+            _ => None
+        }
+    }
+
+    fn parse_keyword(name : &str) -> Result<Color, ColorParseError> {
+        match parse_by_name(name) {
+            Some(c) => Ok(c),
+            None => Err(UnknownKeyword)
+        }
+    }
+
+    fn parse_color_mode(color : &str, lenient : bool) -> Result<Color, ColorParseError> {
+        let mut color = color.trim();
+        // Old IE hacks mark a value with a leading * or _
+        if lenient && (color.starts_with("*") || color.starts_with("_")) {
+            color = color.slice_from(1);
+        }
+        // Longer prefixes go first so no reordering can send rgba( to parse_rgb
+        match color {
+          c if c.starts_with("rgba(") => parse_rgba(c, lenient),
+          c if c.starts_with("rgb(") => parse_rgb(c),
+          c if c.starts_with("hsla(") => parse_hsla(c),
+          c if c.starts_with("hsl(") => parse_hsl(c),
+          c if c.starts_with("hsva(") => parse_hsva(c),
+          c if c.starts_with("hsv(") => parse_hsv(c),
+          c if c.starts_with("hwb(") => parse_hwb(c),
+          c if c.starts_with("#") => parse_hex(c),
+          c => parse_keyword(c)
+        }
+    }
+}
+
+pub fn fail_unrecognized(col : &str) -> Option<Color> {
+    warn!("Unrecognized color %s", col);
+    return None;
+}
+
+/** Returns the CSS keyword naming exactly this color, if there is one */
+pub fn name_of(color : &Color) -> Option<~str> {
+    for &(name, packed) in NAMED_COLORS.iter() {
+        if unpack_rgb(packed) == *color {
+            return Some(name.to_owned());
+        }
+    }
+    None
+}
+
+/** Every CSS color keyword paired with its color, in alphabetical order */
+pub fn all_named() -> ~[(~str, Color)] {
+    NAMED_COLORS.map(|&(name, packed)| (name.to_owned(), unpack_rgb(packed)))
+}
+
+/**
+Suggests the CSS color keyword closest to `name` by edit distance, for
+"did you mean" messages. Nothing is suggested beyond two edits.
+*/
+pub fn suggest_keyword(name : &str) -> Option<~str> {
+    let name = name.trim().to_owned().into_ascii().to_lower().into_str();
+    let mut best = None;
+    let mut best_distance = 3u;
+    for &(keyword, _) in NAMED_COLORS.iter() {
+        let distance = edit_distance(name, keyword);
+        if distance < best_distance {
+            best = Some(keyword.to_owned());
+            best_distance = distance;
+        }
+    }
+    best
+}
+
+/** Levenshtein distance between two ASCII strings */
+fn edit_distance(a : &str, b : &str) -> uint {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let mut prev = vec::from_fn(b.len() + 1, |j| j);
+    for i in range(0u, a.len()) {
+        let mut cur = ~[i + 1];
+        for j in range(0u, b.len()) {
+            let substitution = prev[j] + if a[i] == b[j] { 0 } else { 1 };
+            cur.push(num::min(substitution, num::min(prev[j + 1], cur[j]) + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+pub fn parse_by_name(name : &str) -> Option<Color> {
+    let name = name.trim();
+    if name.len() > LONGEST_COLOR_NAME {
+        return fail_unrecognized(name);
+    }
+
+    // Lowercase into a stack buffer so lookups don't allocate
+    let mut buf = [0u8, ..20];
+    for (i, b) in name.byte_iter().enumerate() {
+        buf[i] = if b >= 'A' as u8 && b <= 'Z' as u8 { b + 32 } else { b };
+    }
+    let key = buf.slice(0, name.len());
+
+    if key == bytes!("transparent") {
+        return Some(rgba(0, 0, 0, 0.0));
+    }
+    match NAMED_COLORS.bsearch(|&(n, _)| n.as_bytes().cmp(&key)) {
+        Some(i) => {
+            let (_, packed) = NAMED_COLORS[i];
+            Some(unpack_rgb(packed))
+        }
+        None => fail_unrecognized(name)
+    }
+}
+
+/** Unpacks a 0xRRGGBB value into an opaque color */
+fn unpack_rgb(packed : u32) -> Color {
+    rgb((packed >> 16) as u8, (packed >> 8) as u8, packed as u8)
+}
+
+// Length of "lightgoldenrodyellow", the longest keyword
+static LONGEST_COLOR_NAME: uint = 20;
+
+// Define the colors specified by css
+define_color!(ALICEBLUE, 240, 248, 255)
+define_color!(ANTIQUEWHITE, 250, 235, 215)
+define_color!(AQUA, 0, 255, 255)
+define_color!(AQUAMARINE, 127, 255, 212)
+define_color!(AZURE, 240, 255, 255)
+define_color!(BEIGE, 245, 245, 220)
+define_color!(BISQUE, 255, 228, 196)
+define_color!(BLACK, 0, 0, 0)
+define_color!(BLANCHEDALMOND, 255, 235, 205)
+define_color!(BLUE, 0, 0, 255)
+define_color!(BLUEVIOLET, 138, 43, 226)
+define_color!(BROWN, 165, 42, 42)
 define_color!(BURLYWOOD, 222, 184, 135)
 define_color!(CADETBLUE, 95, 158, 160)
 define_color!(CHARTREUSE, 127, 255, 0)
@@ -347,6 +2386,7 @@ define_color!(PINK, 255, 192, 203)
 define_color!(PLUM, 221, 160, 221)
 define_color!(POWDERBLUE, 176, 224, 230)
 define_color!(PURPLE, 128, 0, 128)
+define_color!(REBECCAPURPLE, 102, 51, 153)
 define_color!(RED, 255, 0, 0)
 define_color!(ROSYBROWN, 188, 143, 143)
 define_color!(ROYALBLUE, 65, 105, 225)
@@ -376,42 +2416,1400 @@ define_color!(WHITESMOKE, 245, 245, 245)
 define_color!(YELLOW, 255, 255, 0)
 define_color!(YELLOWGREEN, 154, 205, 50)
 
-#[cfg(test)]
-mod test {
-    use super::{rgb, rgba};
-    use super::parsing::parse_color;
+// Every keyword with its packed 0xRRGGBB value, sorted for binary search
+static NAMED_COLORS: &'static [(&'static str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod test {
+    use std::ascii::AsciiStr;
+    use super::{rgb, rgba, hsl, hsla, RED, LIME, BLACK, WHITE};
+    use super::{BLUE, CYAN, MAGENTA, YELLOW, ORANGE, PINK, NAVY, AQUA, hsv, hsva};
+    use super::{hwb, hwba, from_rgba_tuple};
+    use super::{SrgbSpace, HslSpace, HwbSpace, LabSpace, LchSpace, OklabSpace, OklchSpace};
+    use super::{perceptual_length, perceptual_positions, decode_hex_byte};
+    use super::{luminance_row, from_wavelength, suggest_keyword, from_rgb565};
+    use super::{apply_palette_rgba, dither_rgba, same_after_rounding};
+    use super::{from_luv, from_lchuv, mix_hsl, average_hue, apca_contrast};
+    use super::{check_hsl_round_trip, lerp_many, normalize_lightness, diff_palettes};
+    use super::all_named;
+    use extra::sort;
+    use std::hashmap::HashMap;
+    use super::parsing::{parse_color, parse_color_lenient, parse_color_tagged};
+    use super::parsing::parse_color_best_effort;
+    use super::parsing::{KeywordSyntax, RgbSyntax, HexSyntax, parse_color_bytes};
+    use super::parsing::HexParser;
+    use super::parsing::{explain, HslSyntax, RgbaSyntax, HslaSyntax, HsvSyntax, HsvaSyntax};
+    use super::parsing::HwbSyntax;
+    use super::parsing::{WrongNotation, BadComponentCount, BadNumber, OutOfRange, UnknownKeyword};
+    use super::parsing::BadChannelValue;
+    use super::parsing::parse_color_result;
+
+    #[test]
+    fn test_parsing_rgb() {
+        assert!(parse_color("red").unwrap().eq(&parse_color("rgb(255,0,0)").unwrap()));
+        assert!(parse_color("red").unwrap().eq(&parse_color("rgba(255,0,0,1.0)").unwrap()));
+        assert!(parse_color("red").unwrap().eq(&parse_color("rgba(255,0,0,1)").unwrap()));
+        assert!(parse_color("lime").unwrap().eq(&parse_color("rgba(0,255,0,1.00)").unwrap()));
+        assert!(rgb(1u8,2u8,3u8).eq(&parse_color("rgb(1,2,03)").unwrap()));
+        assert!(rgba(15u8,250u8,3u8,0.5).eq(&parse_color("rgba(15,250,3,.5)").unwrap()));
+        assert!(rgba(15u8,250u8,3u8,0.5).eq(&parse_color("rgba(15,250,3,0.5)").unwrap()));
+        assert!(None == parse_color("rbga(1,2,3)"));
+    }
+
+    #[test]
+    fn test_parsing_hsl() {
+        assert!(parse_color("red").unwrap().eq(&parse_color("hsl(0,1,.5)").unwrap()));
+        assert!(parse_color("lime").unwrap().eq(&parse_color("hsl(120.0,1.0,.5)").unwrap()));
+        assert!(parse_color("blue").unwrap().eq(&parse_color("hsl(240.0,1.0,.5)").unwrap()));
+        assert!(parse_color("green").unwrap().eq(&parse_color("hsl(120.0,1.0,.25)").unwrap()));
+        assert!(parse_color("white").unwrap().eq(&parse_color("hsl(1.0,1.,1.0)").unwrap()));
+        assert!(parse_color("white").unwrap().eq(&parse_color("hsl(129.0,0.3,1.0)").unwrap()));
+        assert!(parse_color("black").unwrap().eq(&parse_color("hsl(231.2,0.75,0.0)").unwrap()));
+        assert!(parse_color("black").unwrap().eq(&parse_color("hsl(11.2,0.0,0.0)").unwrap()));
+        assert!(parse_color("gray").unwrap().eq(&parse_color("hsl(0.0,0.0,0.5)").unwrap()));
+        assert!(parse_color("maroon").unwrap().eq(&parse_color("hsl(0.0,1.0,0.25)").unwrap()));
+        assert!(parse_color("purple").unwrap().eq(&parse_color("hsl(300.0,1.0,0.25)").unwrap()));
+        assert!(parse_color("fuchsia").unwrap().eq(&parse_color("hsl(300,1.0,0.5)").unwrap()));
+        assert!(parse_color("olive").unwrap().eq(&parse_color("hsl(60.,1.0,0.25)").unwrap()));
+        assert!(parse_color("yellow").unwrap().eq(&parse_color("hsl(60.,1.0,0.5)").unwrap()));
+        assert!(parse_color("navy").unwrap().eq(&parse_color("hsl(240.0,1.0,.25)").unwrap()));
+        assert!(parse_color("teal").unwrap().eq(&parse_color("hsl(180.0,1.0,.25)").unwrap()));
+        assert!(parse_color("aqua").unwrap().eq(&parse_color("hsl(180.0,1.0,.5)").unwrap()));
+        assert!(None == parse_color("hsl(1,2,3,.4)"));
+    }
+
+    #[test]
+    fn test_parsing_integer_hue() {
+        assert!(parse_color("hsl(120,1.0,.5)") == parse_color("hsl(120.0,1.0,.5)"));
+        assert!(parse_color("hsl(360,1.0,.5)") == parse_color("hsl(360.0,1.0,.5)"));
+        assert!(parse_color("hsla(240,1.0,.5,.5)") == parse_color("hsla(240.0,1.0,.5,.5)"));
+        assert!(parse_color("hsl(0,1.0,.5)") == parse_color("red"));
+    }
+
+    #[test]
+    fn test_to_named_or_hex() {
+        assert!(RED.to_named_or_hex() == ~"red");
+        assert!(rgb(255, 0, 0).to_named_or_hex() == ~"red");
+        assert!(rgb(1, 2, 3).to_named_or_hex() == ~"#010203");
+        assert!(rgb(0x11, 0x22, 0x33).to_named_or_hex() == ~"#123");
+        assert!(rgba(0, 0, 0, 0.0).to_named_or_hex() == ~"transparent");
+    }
+
+    #[test]
+    fn test_apply_matrix() {
+        let identity = [[1.0, 0.0, 0.0],
+                        [0.0, 1.0, 0.0],
+                        [0.0, 0.0, 1.0]];
+        let swap_rg = [[0.0, 1.0, 0.0],
+                       [1.0, 0.0, 0.0],
+                       [0.0, 0.0, 1.0]];
+        let c = rgba(10, 200, 30, 0.5);
+        assert!(c.apply_matrix(identity) == c);
+        assert!(c.apply_matrix(swap_rg) == rgba(200, 10, 30, 0.5));
+        assert!(rgb(255, 255, 255).apply_matrix([[2.0, 0.0, 0.0],
+                                                 [0.0, -1.0, 0.0],
+                                                 [0.0, 0.0, 1.0]]) == rgb(255, 0, 255));
+    }
+
+    #[test]
+    fn test_hue_rotate_filter() {
+        assert!(RED.hue_rotate_filter(0.0) == RED);
+        assert!(RED.hue_rotate_filter(360.0) == RED);
+        // First column of the spec matrix at 120deg: (-0.365, 0.443, -0.362)
+        assert!(RED.hue_rotate_filter(120.0) == rgb(0, 113, 0));
+    }
+
+    #[test]
+    fn test_saturate_filter() {
+        let c = rgba(10, 200, 30, 0.5);
+        assert!(c.saturate_filter(1.0) == c);
+        // 0.213*10 + 0.715*200 + 0.072*30 = 147.29
+        assert!(c.saturate_filter(0.0) == rgba(147, 147, 147, 0.5));
+        assert!(RED.saturate_filter(0.0) == rgb(54, 54, 54));
+        assert!(rgb(200, 100, 100).saturate_filter(2.0).red > 200);
+    }
+
+    #[test]
+    fn test_to_formats() {
+        let formats = RED.to_formats();
+        assert!(formats.hex == ~"#ff0000");
+        assert!(formats.rgb == ~"rgb(255, 0, 0)");
+        assert!(formats.hsl == ~"hsl(0, 100%, 50%)");
+        assert!(formats.oklch == ~"oklch(62.80% 0.258 29.23)");
+        assert!(formats.name == Some(~"red"));
+
+        let formats = rgba(1, 2, 3, 0.5).to_formats();
+        assert!(formats.hex == ~"#01020380");
+        assert!(formats.rgb == ~"rgba(1, 2, 3, 0.5)");
+        assert!(formats.name == None);
+    }
+
+    #[test]
+    fn test_parsing_fractional_alpha() {
+        assert!(parse_color_lenient("rgba(0,0,0,1/2)") == Some(rgba(0, 0, 0, 0.5)));
+        assert!(parse_color_lenient("rgba(0,0,0,0.5)") == Some(rgba(0, 0, 0, 0.5)));
+        assert!(parse_color_lenient("rgba(0,0,0,1/0)") == None);
+        assert!(parse_color("rgba(0,0,0,1/2)") == None);
+    }
+
+    #[test]
+    fn test_perceptual_length() {
+        let near_white = rgb(250, 250, 250);
+        let stops = [BLACK, near_white, WHITE];
+        let length = perceptual_length(stops);
+        let big = BLACK.delta_e(&near_white);
+        assert!(big / length > 0.98);
+        assert!(perceptual_length([RED]) == 0.0);
+
+        let positions = perceptual_positions(stops);
+        assert!(positions[0] == 0.0);
+        assert!(positions[1] > 0.98);
+        assert!(positions[2] == 1.0);
+        assert!(perceptual_positions([RED, RED]) == ~[0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_decode_hex_byte() {
+        assert!(decode_hex_byte('f' as u8, 'f' as u8) == Some(255));
+        assert!(decode_hex_byte('F' as u8, 'f' as u8) == Some(255));
+        assert!(decode_hex_byte('0' as u8, 'a' as u8) == Some(10));
+        assert!(decode_hex_byte('8' as u8, '0' as u8) == Some(128));
+        assert!(decode_hex_byte('g' as u8, '0' as u8) == None);
+        assert!(decode_hex_byte('0' as u8, ' ' as u8) == None);
+    }
+
+    #[test]
+    fn test_luminance_row() {
+        let pixels = [BLACK, RED, WHITE];
+        let mut out = [-1.0, -1.0, -1.0];
+        assert!(luminance_row(pixels, out).is_ok());
+        assert!(out[0] == 0.0);
+        assert!((out[1] - 0.2126).abs() < 1e-9);
+        assert!((out[2] - 1.0).abs() < 1e-9);
+
+        let mut short = [0.0, 0.0];
+        assert!(luminance_row(pixels, short).is_err());
+    }
+
+    #[test]
+    fn test_over_linear() {
+        let half_white = rgba(255, 255, 255, 0.5);
+        let result = half_white.over_linear(&BLACK);
+        // Blending in gamma space would give rgb(128,128,128)
+        assert!(result == rgb(188, 188, 188));
+        assert!(RED.over_linear(&BLACK) == RED);
+        assert!(rgba(0, 0, 255, 0.0).over_linear(&RED) == RED);
+    }
+
+    #[test]
+    fn test_max_contrast_complement() {
+        let c = hsl(200.0, 0.6, 0.5);
+        let naive = hsl(20.0, 0.6, 0.5);
+        let best = c.max_contrast_complement();
+        assert!(c.contrast_ratio(&best) > c.contrast_ratio(&naive));
+
+        let (h, _, _) = best.to_hsl();
+        assert!((h - 20.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_from_wavelength() {
+        assert!(from_wavelength(700.0) == rgb(255, 0, 0));
+        let blue = from_wavelength(450.0);
+        assert!(blue.blue == 255 && blue.red == 0 && blue.green < 100);
+
+        let deep_red = from_wavelength(770.0);
+        assert!(deep_red.red < 255 && deep_red.green == 0);
+        assert!(from_wavelength(780.0).red < deep_red.red);
+        assert!(from_wavelength(380.0).blue < 255);
+        assert!(from_wavelength(300.0) == BLACK);
+        assert!(from_wavelength(900.0) == BLACK);
+    }
+
+    #[test]
+    fn test_suggest_keyword() {
+        assert!(suggest_keyword("red") == Some(~"red"));
+        assert!(suggest_keyword("Gren") == Some(~"green"));
+        assert!(suggest_keyword("lightbleu") == Some(~"lightblue"));
+        assert!(suggest_keyword("xyzzy") == None);
+    }
+
+    #[test]
+    fn test_apply_palette_rgba() {
+        let palette = [BLACK, WHITE, RED];
+        let mut buf = ~[250u8, 240, 245, 255,
+                        20, 10, 0, 255,
+                        200, 30, 40, 255];
+        assert!(apply_palette_rgba(buf, palette).is_ok());
+        assert!(buf == ~[255u8, 255, 255, 255,
+                         0, 0, 0, 255,
+                         255, 0, 0, 255]);
+
+        let mut ragged = ~[0u8, 0, 0];
+        assert!(apply_palette_rgba(ragged, palette).is_err());
+        assert!(apply_palette_rgba(buf, []).is_err());
+    }
+
+    #[test]
+    fn test_dither_rgba() {
+        let (width, height) = (16u, 2u);
+        let mut buf = ~[];
+        for _ in range(0u, height) {
+            for x in range(0u, width) {
+                let v = (x * 255 / (width - 1)) as u8;
+                buf.push_all([v, v, v, 255]);
+            }
+        }
+        assert!(dither_rgba(buf, width, height, [BLACK, WHITE]).is_ok());
+
+        // A hard step would switch from black to white exactly once
+        let mut transitions = 0;
+        for x in range(1u, width) {
+            assert!(buf[x*4] == 0 || buf[x*4] == 255);
+            if buf[x*4] != buf[(x - 1)*4] {
+                transitions += 1;
+            }
+        }
+        assert!(buf[0] == 0 && buf[(width - 1)*4] == 255);
+        assert!(transitions > 1);
+        assert!(dither_rgba(buf, width + 1, height, [BLACK, WHITE]).is_err());
+    }
+
+    #[test]
+    fn test_muted() {
+        let muted = RED.muted();
+        assert!(muted == rgb(174, 115, 105));
+        let (_, s, _) = muted.to_hsl();
+        assert!(s < 0.5);
+
+        let gray = rgb(128, 128, 128);
+        assert!(gray.muted() == gray);
+        assert!(rgba(255, 0, 0, 0.5).muted().alpha == 0.5);
+    }
+
+    #[test]
+    fn test_parsing_calc_alpha() {
+        assert!(parse_color_lenient("rgba(0,0,0,calc(0.5 + 0.25))") == Some(rgba(0, 0, 0, 0.75)));
+        assert!(parse_color_lenient("rgba(0,0,0,calc(1/2))") == Some(rgba(0, 0, 0, 0.5)));
+        assert!(parse_color_lenient("rgba(0,0,0,calc((1 - 0.5) * 50%))") == Some(rgba(0, 0, 0, 0.25)));
+        assert!(parse_color_lenient("rgba(0,0,0,calc(1/0))") == None);
+        assert!(parse_color_lenient("rgba(0,0,0,calc(1px))") == None);
+        assert!(parse_color_lenient("rgba(0,0,0,calc(var(--a)))") == None);
+        assert!(parse_color("rgba(0,0,0,calc(0.5))") == None);
+    }
+
+    #[test]
+    fn test_same_after_rounding() {
+        assert!(same_after_rounding(&hsl(0.0, 1.0, 0.5), &RED));
+        assert!(same_after_rounding(&rgb(254, 1, 0), &RED));
+        assert!(same_after_rounding(&rgba(255, 0, 0, 0.999), &RED));
+        assert!(!same_after_rounding(&rgb(253, 0, 0), &RED));
+        assert!(!same_after_rounding(&rgba(255, 0, 0, 0.99), &RED));
+    }
+
+    #[test]
+    fn test_parsing_tagged_keyword_alias() {
+        let gray = parse_color("gray").unwrap();
+        assert!(parse_color_tagged("grey") == Some((gray, KeywordSyntax(~"gray", Some(~"grey")))));
+        assert!(parse_color_tagged("gray") == Some((gray, KeywordSyntax(~"gray", None))));
+        assert!(parse_color_tagged("LightGrey") ==
+                Some((parse_color("lightgray").unwrap(),
+                      KeywordSyntax(~"lightgray", Some(~"lightgrey")))));
+        assert!(parse_color_tagged("cyan") ==
+                Some((parse_color("aqua").unwrap(), KeywordSyntax(~"aqua", Some(~"cyan")))));
+        assert!(parse_color_tagged("rgb(255,0,0)") == Some((RED, RgbSyntax)));
+        assert!(parse_color_tagged("gery") == None);
+    }
+
+    #[test]
+    fn test_luv() {
+        let (l, u, v) = RED.to_luv();
+        assert!((l - 53.24).abs() < 0.01);
+        assert!(from_luv(l, u, v) == RED);
+        assert!(from_luv(0.0, 0.0, 0.0) == BLACK);
+        let (l, _, _) = WHITE.to_luv();
+        assert!((l - 100.0).abs() < 0.01);
+
+        for c in [RED, rgb(10, 200, 30), rgb(128, 64, 200), rgb(0, 0, 255)].iter() {
+            let (l, ch, h) = c.to_lchuv();
+            assert!(same_after_rounding(&from_lchuv(l, ch, h), c));
+        }
+
+        // Sweeping hue at constant lightness and chroma stays at that lightness
+        // and moves in roughly even perceptual steps
+        let mut sweep = ~[];
+        for i in range(0u, 12) {
+            sweep.push(from_lchuv(60.0, 30.0, i as float * 30.0));
+        }
+        let (mut min_step, mut max_step) = (1.0, 0.0);
+        for i in range(0u, 12) {
+            let (l, ch, _) = sweep[i].to_lchuv();
+            assert!((l - 60.0).abs() < 0.5);
+            assert!((ch - 30.0).abs() < 1.5);
+            let step = sweep[i].delta_e(&sweep[(i + 1) % 12]);
+            min_step = min_step.min(&step);
+            max_step = max_step.max(&step);
+        }
+        assert!(max_step / min_step < 2.0);
+    }
+
+    #[test]
+    fn test_mix_hsl() {
+        let a = hsl(350.0, 1.0, 0.5);
+        let b = hsl(10.0, 1.0, 0.5);
+        let (h, _, _) = mix_hsl(a, b, 0.5, true).to_hsl();
+        assert!(h < 1.0 || h > 359.0);
+        let (h, _, _) = mix_hsl(a, b, 0.5, false).to_hsl();
+        assert!((h - 180.0).abs() < 1.0);
+
+        assert!(mix_hsl(a, b, 0.0, true) == a);
+        assert!(mix_hsl(a, b, 1.0, true) == b);
+        let (_, _, l) = mix_hsl(BLACK, WHITE, 0.5, true).to_hsl();
+        assert!((l - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_average_hue() {
+        let h = average_hue([hsl(350.0, 1.0, 0.5), hsl(10.0, 1.0, 0.5)]).unwrap();
+        assert!(h < 0.5 || h > 359.5);
+        let h = average_hue([hsl(100.0, 1.0, 0.5), hsl(140.0, 1.0, 0.5), rgb(128, 128, 128)]).unwrap();
+        assert!((h - 120.0).abs() < 0.5);
+        assert!(average_hue([BLACK, WHITE, rgb(128, 128, 128)]) == None);
+        assert!(average_hue([]) == None);
+    }
+
+    macro_rules! assert_named(
+        ($($color:ident),+) => {
+            $(
+                let name = stringify!($color);
+                assert!(parse_by_name(name) == Some(super::$color));
+                assert!(parse_by_name(name.to_owned().into_ascii().to_lower().into_str()) ==
+                        Some(super::$color));
+            )+
+        }
+    )
+
+    #[test]
+    fn test_parse_by_name() {
+        use super::parse_by_name;
+
+        assert_named!(
+            ALICEBLUE, ANTIQUEWHITE, AQUA, AQUAMARINE, AZURE,
+            BEIGE, BISQUE, BLACK, BLANCHEDALMOND, BLUE,
+            BLUEVIOLET, BROWN, BURLYWOOD, CADETBLUE, CHARTREUSE, 
+            CHOCOLATE, CORAL, CORNFLOWERBLUE, CORNSILK, CRIMSON,
+            CYAN, DARKBLUE, DARKCYAN, DARKGOLDENROD, DARKGRAY,
+            DARKGREEN, DARKGREY, DARKKHAKI, DARKMAGENTA, DARKOLIVEGREEN,
+            DARKORANGE, DARKORCHID, DARKRED, DARKSALMON, DARKSEAGREEN,
+            DARKSLATEBLUE, DARKSLATEGRAY, DARKSLATEGREY, DARKTURQUOISE, DARKVIOLET,
+            DEEPPINK, DEEPSKYBLUE, DIMGRAY, DIMGREY, DODGERBLUE,
+            FIREBRICK, FLORALWHITE, FORESTGREEN, FUCHSIA, GAINSBORO,
+            GHOSTWHITE, GOLD, GOLDENROD, GRAY, GREY,
+            GREEN, GREENYELLOW, HONEYDEW, HOTPINK, INDIANRED,
+            INDIGO, IVORY, KHAKI, LAVENDER, LAVENDERBLUSH,
+            LAWNGREEN, LEMONCHIFFON, LIGHTBLUE, LIGHTCORAL, LIGHTCYAN,
+            LIGHTGOLDENRODYELLOW, LIGHTGRAY, LIGHTGREEN, LIGHTGREY, LIGHTPINK,
+            LIGHTSALMON, LIGHTSEAGREEN, LIGHTSKYBLUE, LIGHTSLATEGRAY, LIGHTSLATEGREY,
+            LIGHTSTEELBLUE, LIGHTYELLOW, LIME, LIMEGREEN, LINEN,
+            MAGENTA, MAROON, MEDIUMAQUAMARINE, MEDIUMBLUE, MEDIUMORCHID,
+            MEDIUMPURPLE, MEDIUMSEAGREEN, MEDIUMSLATEBLUE, MEDIUMSPRINGGREEN, MEDIUMTURQUOISE,
+            MEDIUMVIOLETRED, MIDNIGHTBLUE, MINTCREAM, MISTYROSE, MOCCASIN,
+            NAVAJOWHITE, NAVY, OLDLACE, OLIVE, OLIVEDRAB,
+            ORANGE, ORANGERED, ORCHID, PALEGOLDENROD, PALEGREEN,
+            PALETURQUOISE, PALEVIOLETRED, PAPAYAWHIP, PEACHPUFF, PERU,
+            PINK, PLUM, POWDERBLUE, PURPLE, REBECCAPURPLE, RED,
+            ROSYBROWN, ROYALBLUE, SADDLEBROWN, SALMON, SANDYBROWN,
+            SEAGREEN, SEASHELL, SIENNA, SILVER, SKYBLUE,
+            SLATEBLUE, SLATEGRAY, SLATEGREY, SNOW, SPRINGGREEN,
+            STEELBLUE, TAN, TEAL, THISTLE, TOMATO,
+            TURQUOISE, VIOLET, WHEAT, WHITE, WHITESMOKE,
+            YELLOW, YELLOWGREEN);
+
+        assert!(parse_by_name(" Red ") == Some(RED));
+        assert!(parse_by_name("lightgoldenrodyellowish") == None);
+        assert!(parse_by_name("reds") == None);
+        assert!(parse_by_name("") == None);
+    }
+
+    #[test]
+    fn test_normalize_alpha_from_byte() {
+        use super::Color;
+
+        let misread = Color { red: 10, green: 20, blue: 30, alpha: 128.0 };
+        let fixed = misread.normalize_alpha_from_byte();
+        assert!(fixed.red == 10 && fixed.green == 20 && fixed.blue == 30);
+        assert!((fixed.alpha - 128.0 / 255.0).abs() < 1e-9);
+
+        let way_off = Color { red: 0, green: 0, blue: 0, alpha: 1000.0 };
+        assert!(way_off.normalize_alpha_from_byte().alpha == 1.0);
+        assert!(rgba(1, 2, 3, 0.5).normalize_alpha_from_byte() == rgba(1, 2, 3, 0.5));
+        assert!(RED.normalize_alpha_from_byte() == RED);
+    }
+
+    #[test]
+    fn test_parsing_bytes() {
+        assert!(parse_color_bytes(bytes!("red")) == Some(RED));
+        assert!(parse_color_bytes(bytes!("rgba(255,0,0,0.5)")) == parse_color("rgba(255,0,0,0.5)"));
+        assert!(parse_color_bytes(bytes!("bogus")) == None);
+        assert!(parse_color_bytes([0x72, 0x65, 0x64, 0xff]) == None);
+    }
+
+    #[test]
+    fn test_apca_contrast() {
+        // Reference values from the APCA 0.0.98G-4g calculator
+        assert!((apca_contrast(&BLACK, &WHITE) - 106.04).abs() < 0.01);
+        assert!((apca_contrast(&WHITE, &BLACK) + 107.88).abs() < 0.01);
+        assert!((apca_contrast(&rgb(0x88, 0x88, 0x88), &WHITE) - 63.06).abs() < 0.01);
+        assert!(apca_contrast(&RED, &RED) == 0.0);
+    }
+
+    #[test]
+    fn test_rgb565() {
+        assert!(RED.to_rgb565() == 0xf800);
+        assert!(WHITE.to_rgb565() == 0xffff);
+        assert!(BLACK.to_rgb565() == 0);
+        assert!(from_rgb565(0xf800) == RED);
+        assert!(from_rgb565(0xffff) == WHITE);
+
+        let mid = rgb(100, 150, 200);
+        let back = from_rgb565(mid.to_rgb565());
+        assert!(back == rgb(99, 150, 198));
+        assert!(from_rgb565(back.to_rgb565()) == back);
+    }
 
     #[test]
-    fn test_parsing_rgb() {
-        assert!(parse_color("red").unwrap().eq(&parse_color("rgb(255,0,0)").unwrap()));
-        assert!(parse_color("red").unwrap().eq(&parse_color("rgba(255,0,0,1.0)").unwrap()));
-        assert!(parse_color("red").unwrap().eq(&parse_color("rgba(255,0,0,1)").unwrap()));
-        assert!(parse_color("lime").unwrap().eq(&parse_color("rgba(0,255,0,1.00)").unwrap()));
-        assert!(rgb(1u8,2u8,3u8).eq(&parse_color("rgb(1,2,03)").unwrap()));
-        assert!(rgba(15u8,250u8,3u8,0.5).eq(&parse_color("rgba(15,250,3,.5)").unwrap()));
-        assert!(rgba(15u8,250u8,3u8,0.5).eq(&parse_color("rgba(15,250,3,0.5)").unwrap()));
-        assert!(None == parse_color("rbga(1,2,3)"));
+    fn test_to_custom_property() {
+        assert!(RED.to_custom_property("brand") == ~"--brand: red;");
+        assert!(rgb(1, 2, 3).to_custom_property("x") == ~"--x: #010203;");
     }
 
     #[test]
-    fn test_parsing_hsl() {
-        assert!(parse_color("red").unwrap().eq(&parse_color("hsl(0,1,.5)").unwrap()));
-        assert!(parse_color("lime").unwrap().eq(&parse_color("hsl(120.0,1.0,.5)").unwrap()));
-        assert!(parse_color("blue").unwrap().eq(&parse_color("hsl(240.0,1.0,.5)").unwrap()));
-        assert!(parse_color("green").unwrap().eq(&parse_color("hsl(120.0,1.0,.25)").unwrap()));
-        assert!(parse_color("white").unwrap().eq(&parse_color("hsl(1.0,1.,1.0)").unwrap()));
-        assert!(parse_color("white").unwrap().eq(&parse_color("hsl(129.0,0.3,1.0)").unwrap()));
-        assert!(parse_color("black").unwrap().eq(&parse_color("hsl(231.2,0.75,0.0)").unwrap()));
-        assert!(parse_color("black").unwrap().eq(&parse_color("hsl(11.2,0.0,0.0)").unwrap()));
-        assert!(parse_color("gray").unwrap().eq(&parse_color("hsl(0.0,0.0,0.5)").unwrap()));
-        assert!(parse_color("maroon").unwrap().eq(&parse_color("hsl(0.0,1.0,0.25)").unwrap()));
-        assert!(parse_color("purple").unwrap().eq(&parse_color("hsl(300.0,1.0,0.25)").unwrap()));
-        assert!(parse_color("fuchsia").unwrap().eq(&parse_color("hsl(300,1.0,0.5)").unwrap()));
-        assert!(parse_color("olive").unwrap().eq(&parse_color("hsl(60.,1.0,0.25)").unwrap()));
-        assert!(parse_color("yellow").unwrap().eq(&parse_color("hsl(60.,1.0,0.5)").unwrap()));
-        assert!(parse_color("navy").unwrap().eq(&parse_color("hsl(240.0,1.0,.25)").unwrap()));
-        assert!(parse_color("teal").unwrap().eq(&parse_color("hsl(180.0,1.0,.25)").unwrap()));
-        assert!(parse_color("aqua").unwrap().eq(&parse_color("hsl(180.0,1.0,.5)").unwrap()));
-        assert!(None == parse_color("hsl(1,2,3,.4)"));
+    fn test_parsing_hex() {
+        assert!(parse_color("#ff0000") == Some(RED));
+        assert!(parse_color("#FF0000") == Some(RED));
+        assert!(parse_color("#010203") == Some(rgb(1, 2, 3)));
+        assert!(parse_color("#ff000") == None);
+        assert!(parse_color("#ff00000") == None);
+        assert!(parse_color("#gg0000") == None);
+        assert!(parse_color("#") == None);
+        assert!(parse_color_tagged("#ff0000") == Some((RED, HexSyntax)));
+    }
+
+    #[test]
+    fn test_parsing_short_hex() {
+        assert!(parse_color("#f00") == Some(RED));
+        assert!(parse_color("#0f0") == Some(LIME));
+        assert!(parse_color("#FFF") == Some(WHITE));
+        assert!(parse_color("#123") == Some(rgb(0x11, 0x22, 0x33)));
+        assert!(parse_color("#f0") == None);
+        assert!(parse_color("#f000f") == None);
+        assert!(parse_color("#fg0") == None);
+    }
+
+    #[test]
+    fn test_on_color() {
+        // Translucent black over white looks mid-gray, which reads best with black text
+        let scrim = rgba(0, 0, 0, 0.5);
+        assert!(scrim.on_color(&WHITE) == BLACK);
+        assert!(scrim.on_color(&BLACK) == WHITE);
+        assert!(rgba(255, 255, 255, 0.0).on_color(&BLACK) == WHITE);
+        assert!(WHITE.on_color(&BLACK) == BLACK);
+        assert!(rgba(0, 0, 0, 0.5).on_color(&WHITE).alpha == 1.0);
+    }
+
+    #[test]
+    fn test_parsing_hex_alpha() {
+        let clear = parse_color("#00000000").unwrap();
+        assert!(clear == rgba(0, 0, 0, 0.0));
+        assert!(clear.alpha == 0.0);
+        assert!(parse_color("#ffffffff") == Some(WHITE));
+        assert!(parse_color("#ff000080") == Some(rgba(255, 0, 0, 128.0 / 255.0)));
+        assert!(parse_color("#f008") == Some(rgba(255, 0, 0, 136.0 / 255.0)));
+        assert!(parse_color("#fff0") == Some(rgba(255, 255, 255, 0.0)));
+        assert!(parse_color("#ff00008") == None);
+        assert!(parse_color("#ff0000zz") == None);
+
+        for c in [rgba(255, 0, 0, 128.0 / 255.0), rgba(1, 2, 3, 0.0), RED].iter() {
+            assert!(parse_color(c.to_hex()) == Some(*c));
+            assert!(parse_color(c.to_named_or_hex()) == Some(*c));
+        }
+    }
+
+    #[test]
+    fn test_hsl_round_trip() {
+        match check_hsl_round_trip(5) {
+            Some((c, back)) => fail!(fmt!("%s came back from hsl as %s", c.print(), back.print())),
+            None => ()
+        }
+    }
+
+    #[test]
+    fn test_parsing_transparent() {
+        let clear = parse_color("transparent").unwrap();
+        assert!(clear.red == 0 && clear.green == 0 && clear.blue == 0);
+        assert!(clear.alpha == 0.0);
+        assert!(parse_color("Transparent") == Some(clear));
+        assert!(parse_color(clear.to_named_or_hex()) == Some(clear));
+    }
+
+    #[test]
+    fn test_lerp_many() {
+        assert!(lerp_many(BLACK, WHITE, 3, false) == ~[BLACK, rgb(128, 128, 128), WHITE]);
+        // Halfway in linear light is much lighter once re-encoded
+        assert!(lerp_many(BLACK, WHITE, 3, true) == ~[BLACK, rgb(188, 188, 188), WHITE]);
+        assert!(lerp_many(RED, WHITE, 1, false) == ~[RED]);
+        assert!(lerp_many(RED, WHITE, 0, true) == ~[]);
+
+        let fades = lerp_many(RED, rgba(255, 0, 0, 0.0), 5, false);
+        assert!(fades.len() == 5);
+        assert!(fades[2].alpha == 0.5);
+    }
+
+    #[test]
+    fn test_parsing_hsl_percentages() {
+        assert!(parse_color("hsl(120,100%,50%)") == Some(LIME));
+        assert!(parse_color("hsl(120,100%,.5)") == Some(LIME));
+        assert!(parse_color("hsla(120,100%,50%,0.5)") == Some(rgba(0, 255, 0, 0.5)));
+        assert!(parse_color("hsl(120,1.0,0.5)") == Some(LIME));
+        assert!(parse_color("hsl(120,%,50%)") == None);
+    }
+
+    #[test]
+    fn test_clips_in() {
+        use super::{GamutSrgb, GamutGrayscale, GamutPrimaries};
+
+        assert!(RED.clips_in(GamutGrayscale));
+        assert!(!rgb(128, 128, 128).clips_in(GamutGrayscale));
+        assert!(!WHITE.clips_in(GamutGrayscale));
+        assert!(!BLACK.clips_in(GamutGrayscale));
+
+        for c in [RED, LIME, rgb(0, 0, 255), rgb(10, 200, 30), WHITE].iter() {
+            assert!(!c.clips_in(GamutSrgb));
+        }
+
+        let narrow = GamutPrimaries((0.55, 0.33), (0.32, 0.50), (0.18, 0.12));
+        assert!(RED.clips_in(narrow));
+        assert!(!rgb(128, 128, 128).clips_in(narrow));
+    }
+
+    #[test]
+    fn test_parsing_rgb_percentages() {
+        assert!(parse_color("rgb(100%,0%,0%)") == Some(RED));
+        assert!(parse_color("rgb(100%,0,0)") == Some(RED));
+        assert!(parse_color("rgb(50%,50%,50%)") == Some(rgb(128, 128, 128)));
+        assert!(parse_color("rgba(100%,0%,0%,0.5)") == Some(rgba(255, 0, 0, 0.5)));
+        assert!(parse_color("rgb(%,0,0)") == None);
+    }
+
+    #[test]
+    fn test_hex_parser() {
+        let half_red = rgba(255, 0, 0, 128.0 / 255.0);
+        let argb = HexParser::new().alpha_first(true);
+        assert!(argb.parse("#80ff0000") == Some(half_red));
+        assert!(argb.parse("#8f00") == Some(rgba(255, 0, 0, 136.0 / 255.0)));
+        assert!(argb.parse("#ff0000") == Some(RED));
+        assert!(HexParser::new().parse("#ff000080") == Some(half_red));
+        assert!(HexParser::new().parse("#80ff0000") == Some(rgba(128, 255, 0, 0.0)));
+
+        assert!(HexParser::new().parse("ff0000") == None);
+        let bare = HexParser::new().require_hash(false);
+        assert!(bare.parse("ff0000") == Some(RED));
+        assert!(bare.parse("#ff0000") == Some(RED));
+        assert!(bare.parse("f00") == Some(RED));
+        assert!(bare.parse("ff00") == Some(rgba(255, 255, 0, 0.0)));
+    }
+
+    #[test]
+    fn test_to_hsl_rounded() {
+        assert!(LIME.to_hsl_rounded() == (120, 100, 50));
+        assert!(RED.to_hsl_rounded() == (0, 100, 50));
+        assert!(rgb(128, 128, 128).to_hsl_rounded() == (0, 0, 50));
+        // A hue of 359.76 rounds to 360, which wraps to 0
+        assert!(rgb(255, 0, 1).to_hsl_rounded() == (0, 100, 50));
+    }
+
+    #[test]
+    fn test_parsing_whitespace() {
+        assert!(parse_color("  red") == Some(RED));
+        assert!(parse_color(" rgb(1,2,3) ") == Some(rgb(1, 2, 3)));
+        assert!(parse_color("rgb( 255 ,  0,0 )") == Some(RED));
+        assert!(parse_color("rgba(255, 0 , 0,  0.5 )") == Some(rgba(255, 0, 0, 0.5)));
+        assert!(parse_color("hsl( 120 , 100% ,50%)") == Some(LIME));
+        assert!(parse_color("\thsla(120,  1.0, 0.5 , 1 )\n") == Some(LIME));
+        assert!(parse_color(" #ff0000 ") == Some(RED));
+    }
+
+    #[test]
+    fn test_parsing_best_effort() {
+        let messy = "\" light-blue \"";
+        assert!(parse_color(messy) == None);
+        assert!(parse_color_best_effort(messy) == parse_color("lightblue"));
+        assert!(parse_color_best_effort("red") == Some(RED));
+        assert!(parse_color_best_effort("%23ff0000") == Some(RED));
+        assert!(parse_color_best_effort("rgb (255, 0, 0)") == Some(RED));
+        assert!(parse_color_best_effort("'rgb(255px, 0px, 0px)'") == Some(RED));
+        assert!(parse_color_best_effort("rgba(0,0,0,1/2)") == Some(rgba(0, 0, 0, 0.5)));
+        assert!(parse_color_best_effort("not-a-color") == None);
+    }
+
+    #[test]
+    fn test_parsing_clamped_channels() {
+        assert!(parse_color("rgb(300,0,0)") == Some(RED));
+        assert!(parse_color("rgb(-5,0,0)") == Some(BLACK));
+        assert!(parse_color("rgba(255,1000,-1,1)") == Some(rgba(255, 255, 0, 1.0)));
+    }
+
+    #[test]
+    fn test_w3c_differences() {
+        assert!(BLACK.brightness_difference(&WHITE) == 255);
+        assert!(WHITE.brightness_difference(&BLACK) == 255);
+        assert!(BLACK.color_difference(&WHITE) == 765);
+        assert!(BLACK.brightness_difference(&WHITE) >= 125);
+        assert!(BLACK.color_difference(&WHITE) >= 500);
+        // Red is bright enough by neither measure on black
+        assert!(RED.brightness_difference(&BLACK) == 76);
+        assert!(RED.color_difference(&BLACK) == 255);
+    }
+
+    #[test]
+    fn test_alpha_clamped() {
+        assert!(rgba(255, 0, 0, 2.0).alpha == 1.0);
+        assert!(rgba(255, 0, 0, -0.5).alpha == 0.0);
+        assert!(hsla(0.0, 1.0, 0.5, 7.0).alpha == 1.0);
+        assert!(hsla(0.0, 1.0, 0.5, -1.0).alpha == 0.0);
+        assert!(parse_color("rgba(255,0,0,2.0)").unwrap().alpha == 1.0);
+        assert!(parse_color("rgba(255,0,0,-1)").unwrap().alpha == 0.0);
+        assert!(parse_color("hsla(0,100%,50%,1.5)").unwrap().alpha == 1.0);
+    }
+
+    #[test]
+    fn test_normalize_lightness() {
+        let palette = [rgb(40, 40, 40), rgb(90, 60, 60), rgb(120, 120, 140)];
+        let shifted = normalize_lightness(palette, 0.6);
+        assert!(shifted.len() == 3);
+        let ls = shifted.map(|c| { let (l, _, _) = c.to_oklab(); l });
+        let mean = (ls[0] + ls[1] + ls[2]) / 3.0;
+        assert!((mean - 0.6).abs() < 0.01);
+        assert!(ls[0] < ls[1] && ls[1] < ls[2]);
+        assert!(normalize_lightness([], 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_to_hex() {
+        assert!(RED.to_hex() == ~"#ff0000");
+        assert!(rgb(1, 2, 171).to_hex() == ~"#0102ab");
+        assert!(rgba(255, 0, 0, 0.5).to_hex() == ~"#ff000080");
+        assert!(rgba(0, 0, 0, 0.0).to_hex() == ~"#00000000");
+    }
+
+    #[test]
+    fn test_to_argb_hex() {
+        assert!(rgba(255, 0, 0, 0.5).to_argb_hex() == ~"#80ff0000");
+        assert!(rgba(255, 0, 0, 0.5).to_hex_alpha() == ~"#ff000080");
+        assert!(RED.to_argb_hex() == ~"#ffff0000");
+        assert!(RED.to_hex_alpha() == ~"#ff0000ff");
+        let android = HexParser::new().alpha_first(true);
+        assert!(android.parse(rgba(1, 2, 3, 0.5).to_argb_hex()) == Some(rgba(1, 2, 3, 128.0 / 255.0)));
+    }
+
+    #[test]
+    fn test_to_str() {
+        assert!(RED.to_str() == ~"rgba(255,0,0,1)");
+        assert!(rgba(1, 2, 3, 0.5).to_str() == ~"rgba(1,2,3,0.5)");
+        assert!(RED.print() == RED.to_str());
+    }
+
+    #[test]
+    fn test_explain() {
+        let attempts = explain("#f00");
+        assert!(attempts == ~[(RgbSyntax, Err(WrongNotation)),
+                              (RgbaSyntax, Err(WrongNotation)),
+                              (HslSyntax, Err(WrongNotation)),
+                              (HslaSyntax, Err(WrongNotation)),
+                              (HsvSyntax, Err(WrongNotation)),
+                              (HsvaSyntax, Err(WrongNotation)),
+                              (HwbSyntax, Err(WrongNotation)),
+                              (HexSyntax, Ok(RED)),
+                              (KeywordSyntax(~"#f00", None), Err(UnknownKeyword))]);
+
+        let attempts = explain("rgb(1,2)");
+        assert!(attempts[0] == (RgbSyntax, Err(BadComponentCount)));
+
+        let attempts = explain("Grey");
+        assert!(attempts[8] == (KeywordSyntax(~"gray", Some(~"grey")),
+                                Ok(rgb(128, 128, 128))));
+    }
+
+    #[test]
+    fn test_parsing_ie_hacks() {
+        assert!(parse_color_lenient("*red") == Some(RED));
+        assert!(parse_color_lenient("_#ff0000") == Some(RED));
+        assert!(parse_color(" *red") == None);
+        assert!(parse_color("_red") == None);
+        assert!(parse_color_lenient("**red") == None);
+    }
+
+    #[test]
+    fn test_clone() {
+        let parsed = parse_color("rgba(10,20,30,0.25)").unwrap();
+        let copy = parsed.clone();
+        assert!(copy == parsed);
+        let palette = ~[RED, parsed];
+        assert!(palette.clone() == palette);
+    }
+
+    #[test]
+    fn test_primary_secondary() {
+        assert!(RED.is_primary() && LIME.is_primary() && BLUE.is_primary());
+        assert!(CYAN.is_secondary() && MAGENTA.is_secondary() && YELLOW.is_secondary());
+        assert!(!RED.is_secondary() && !YELLOW.is_primary());
+        assert!(!ORANGE.is_primary() && !ORANGE.is_secondary());
+        assert!(!BLACK.is_primary() && !BLACK.is_secondary());
+        assert!(!WHITE.is_primary() && !WHITE.is_secondary());
+    }
+
+    #[test]
+    fn test_hsv() {
+        assert!(hsv(0.0, 1.0, 1.0) == RED);
+        assert!(hsv(0.0, 0.0, 1.0) == WHITE);
+        assert!(hsv(0.0, 0.0, 0.0) == BLACK);
+        assert!(hsv(120.0, 1.0, 1.0) == LIME);
+        assert!(hsv(240.0, 1.0, 1.0) == BLUE);
+        assert!(hsv(60.0, 1.0, 1.0) == YELLOW);
+        assert!(hsv(-60.0, 1.0, 1.0) == MAGENTA);
+        assert!(hsv(39.0, 1.0, 1.0) == rgb(255, 166, 0));
+        assert!(hsva(180.0, 1.0, 1.0, 0.5) == rgba(0, 255, 255, 0.5));
+        assert!(hsv(0.0, 0.5, 0.5) == rgb(128, 64, 64));
+    }
+
+    #[test]
+    fn test_parsing_hsv() {
+        assert!(parse_color("hsv(120,1,1)") == Some(LIME));
+        assert!(parse_color("hsv(0, 100%, 100%)") == Some(RED));
+        assert!(parse_color("hsva(240,1,1,0.5)") == Some(rgba(0, 0, 255, 0.5)));
+        assert!(parse_color("hsv(120,1)") == None);
+        assert!(parse_color("hsva(120,1,1)") == None);
+        assert!(parse_color_tagged("hsv(120,1,1)") == Some((LIME, HsvSyntax)));
+    }
+
+    #[test]
+    fn test_chroma() {
+        assert!(RED.chroma() > 0.25);
+        assert!(BLUE.chroma() > RED.chroma());
+        assert!(rgb(128, 128, 128).chroma() < 1e-4);
+        assert!(WHITE.chroma() < 1e-4);
+        assert!(PINK.chroma() > 0.05 && PINK.chroma() < 0.15);
+    }
+
+    #[test]
+    fn test_to_hsl() {
+        fn near(actual : (float, float, float), expected : (float, float, float)) -> bool {
+            let ((h, s, l), (eh, es, el)) = (actual, expected);
+            (h - eh).abs() < 1e-6 && (s - es).abs() < 1e-6 && (l - el).abs() < 1e-6
+        }
+        assert!(near(RED.to_hsl(), (0.0, 1.0, 0.5)));
+        assert!(near(LIME.to_hsl(), (120.0, 1.0, 0.5)));
+        assert!(near(BLUE.to_hsl(), (240.0, 1.0, 0.5)));
+        assert!(near(MAGENTA.to_hsl(), (300.0, 1.0, 0.5)));
+        assert!(near(WHITE.to_hsl(), (0.0, 0.0, 1.0)));
+        assert!(near(rgb(51, 51, 51).to_hsl(), (0.0, 0.0, 0.2)));
+    }
+
+    #[test]
+    fn test_lighten_darken() {
+        assert!(BLACK.lighten(1.0) == WHITE);
+        assert!(WHITE.darken(1.0) == BLACK);
+        assert!(RED.lighten(0.25) == rgb(255, 128, 128));
+        assert!(RED.darken(0.25) == rgb(128, 0, 0));
+        assert!(RED.lighten(2.0) == WHITE);
+        assert!(rgba(255, 0, 0, 0.5).darken(0.5) == rgba(0, 0, 0, 0.5));
+    }
+
+    #[test]
+    fn test_convert() {
+        let oklch = RED.convert(OklchSpace);
+        assert!(oklch.space == OklchSpace);
+        assert!(oklch.components == RED.to_oklch());
+        assert!(oklch.to_color() == RED);
+        assert!(RED.convert(HslSpace).components == RED.to_hsl());
+        assert!(RED.convert(OklabSpace).components == RED.to_oklab());
+
+        let (l, a, b) = RED.convert(LabSpace).components;
+        assert!((l - 53.24).abs() < 0.01 && (a - 80.09).abs() < 0.01 && (b - 67.20).abs() < 0.01);
+        let (h, w, k) = rgb(255, 128, 128).convert(HwbSpace).components;
+        assert!(h == 0.0 && (w - 128.0 / 255.0).abs() < 1e-9 && k == 0.0);
+
+        let samples = [RED, LIME, BLUE, PINK, ORANGE, WHITE, BLACK, rgb(12, 200, 99),
+                       rgba(40, 50, 60, 0.25)];
+        let spaces = [SrgbSpace, HslSpace, HwbSpace, LabSpace, LchSpace, OklabSpace, OklchSpace];
+        for c in samples.iter() {
+            for space in spaces.iter() {
+                assert!(c.convert(*space).to_color() == *c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_monochromatic() {
+        for base in [rgb(70, 130, 180), RED, BLUE, ORANGE].iter() {
+            let (_, _, hue) = base.to_oklch();
+            let shades = base.monochromatic(6);
+            assert!(shades.len() == 6);
+            let mut last = 0.0;
+            for shade in shades.iter() {
+                let (l, _, h) = shade.to_oklch();
+                assert!((h - hue).abs() < 2.0);
+                assert!(l > last);
+                last = l;
+            }
+        }
+        assert!(RED.monochromatic(1) == ~[RED]);
+        assert!(RED.monochromatic(0).is_empty());
+        assert!(rgba(255, 0, 0, 0.5).monochromatic(3).iter().all(|c| c.alpha == 0.5));
+    }
+
+    #[test]
+    fn test_saturate_desaturate() {
+        assert!(RED.desaturate(1.0) == rgb(128, 128, 128));
+        assert!(rgb(51, 102, 153).desaturate(1.0) == rgb(102, 102, 102));
+        assert!(RED.desaturate(0.5) == rgb(191, 64, 64));
+        assert!(rgb(191, 64, 64).saturate(0.5) == RED);
+        assert!(RED.saturate(1.0) == RED);
+        assert!(rgba(0, 0, 255, 0.25).desaturate(1.0).alpha == 0.25);
+    }
+
+    #[test]
+    fn test_mix() {
+        // 127.5 rounds half away from zero
+        assert!(BLACK.mix(&WHITE, 0.5) == rgb(128, 128, 128));
+        assert!(BLACK.mix(&WHITE, 0.0) == BLACK);
+        assert!(BLACK.mix(&WHITE, 1.0) == WHITE);
+        assert!(RED.mix(&BLUE, 0.25) == rgb(191, 0, 64));
+        assert!(rgba(0, 0, 0, 0.0).mix(&RED, 0.5) == rgba(128, 0, 0, 0.5));
+    }
+
+    #[test]
+    #[cfg(quickcheck)]
+    fn test_hex_round_trip_property() {
+        use std::rand::{Rng, XorShiftRng};
+        use super::Color;
+
+        // Fixed seed, so a failure can be reproduced
+        let mut rng = XorShiftRng::new_seeded(0x193a6754, 0xa8a7d469, 0x97830e05, 0x113ba7bb);
+        for _ in range(0, 5000) {
+            let c : Color = rng.gen();
+            let back = parse_color(c.to_hex()).unwrap();
+            assert!(back.red == c.red && back.green == c.green && back.blue == c.blue,
+                    fmt!("%s came back as %s", c.to_str(), back.to_str()));
+            // Alpha only survives to the nearest 1/255
+            assert!((back.alpha - c.alpha).abs() <= 0.5 / 255.0 + 1e-9,
+                    fmt!("%s came back as %s", c.to_str(), back.to_str()));
+        }
+    }
+
+    #[test]
+    fn test_diff_palettes() {
+        let theme = [RED, LIME, BLUE, rgb(40, 40, 40)];
+        assert!(diff_palettes(theme, theme, 0.0).is_empty());
+
+        let changed = [RED, LIME, rgb(0, 0, 200), rgb(40, 40, 41)];
+        let diffs = diff_palettes(theme, changed, 0.01);
+        assert!(diffs.len() == 1);
+        let (i, d) = diffs[0];
+        assert!(i == 2 && d == BLUE.delta_e(&rgb(0, 0, 200)));
+
+        let diffs = diff_palettes(theme, [RED, LIME], 0.01);
+        assert!(diffs.len() == 2);
+        let (i, d) = diffs[0];
+        assert!(i == 2 && d > 1e300);
+    }
+
+    #[test]
+    fn test_over() {
+        assert!(rgba(255, 0, 0, 0.5).over(&WHITE) == rgb(255, 128, 128));
+        assert!(rgba(255, 0, 0, 0.0).over(&WHITE) == WHITE);
+        assert!(RED.over(&WHITE) == RED);
+        let glass = rgba(10, 20, 30, 0.4);
+        assert!(rgba(0, 0, 0, 0.0).over(&glass) == glass);
+        assert!((rgba(0, 0, 255, 0.5).over(&rgba(255, 0, 0, 0.5)).alpha - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_project_to_hue() {
+        for gray in [BLACK, WHITE, rgb(128, 128, 128), rgb(30, 30, 30)].iter() {
+            assert!(gray.project_to_hue(120.0).chroma() < 0.002);
+        }
+
+        let red = rgb(180, 40, 40);
+        let (_, _, blue_hue) = BLUE.to_oklch();
+        let projected = red.project_to_hue(blue_hue);
+        let (l, _, h) = projected.to_oklch();
+        let (red_l, _, _) = red.to_oklch();
+        assert!((l - red_l).abs() < 0.01);
+        assert!((h - blue_hue).abs() < 1.0);
+        assert!(projected.blue > projected.red && projected.blue > projected.green);
+    }
+
+    #[test]
+    fn test_luminance() {
+        assert!(BLACK.luminance().abs() < 1e-9);
+        assert!((WHITE.luminance() - 1.0).abs() < 1e-9);
+        assert!((RED.luminance() - 0.2126).abs() < 1e-9);
+        assert!((rgb(128, 128, 128).luminance() - 0.2158605).abs() < 1e-6);
+        // Channel 10 is below both thresholds, 11 above both
+        assert!((rgb(10, 10, 10).luminance() - 10.0 / 255.0 / 12.92).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_contrast_ratio() {
+        assert!((BLACK.contrast_ratio(&WHITE) - 21.0).abs() < 1e-9);
+        assert!((WHITE.contrast_ratio(&BLACK) - 21.0).abs() < 1e-9);
+        assert!(RED.contrast_ratio(&RED) == 1.0);
+        // #767676 is the lightest gray that passes 4.5:1 on white
+        assert!(rgb(0x76, 0x76, 0x76).contrast_ratio(&WHITE) >= 4.5);
+        assert!(rgb(0x77, 0x77, 0x77).contrast_ratio(&WHITE) < 4.5);
+    }
+
+    #[test]
+    fn test_is_light_dark() {
+        assert!(YELLOW.is_light() && !YELLOW.is_dark());
+        assert!(NAVY.is_dark() && !NAVY.is_light());
+        assert!(WHITE.is_light() && BLACK.is_dark());
+        // Mid gray is only about 22% luminance
+        assert!(rgb(128, 128, 128).is_dark());
+    }
+
+    #[test]
+    fn test_with_alpha() {
+        let half = RED.with_alpha(0.5);
+        assert!(half.red == 255 && half.green == 0 && half.blue == 0 && half.alpha == 0.5);
+        assert!(half.with_alpha(1.0) == RED);
+        assert!(RED.with_alpha(3.0).alpha == 1.0);
+        assert!(RED.with_alpha(-3.0).alpha == 0.0);
+    }
+
+    #[test]
+    fn test_rotate_hue() {
+        assert!(RED.rotate_hue(120.0) == LIME);
+        assert!(RED.rotate_hue(-120.0) == BLUE);
+        assert!(LIME.rotate_hue(600.0) == RED);
+        for c in [RED, rgb(12, 200, 99), rgb(70, 130, 180), rgba(1, 2, 3, 0.5)].iter() {
+            assert!(c.rotate_hue(0.0) == *c);
+            assert!(c.rotate_hue(360.0) == *c);
+            assert!(c.rotate_hue(-360.0) == *c);
+        }
+    }
+
+    #[test]
+    fn test_complement() {
+        assert!(RED.complement() == CYAN);
+        assert!(YELLOW.complement() == BLUE);
+        let c = hsl(30.0, 0.6, 0.4);
+        let (h, s, l) = c.to_hsl();
+        let (ch, cs, cl) = c.complement().to_hsl();
+        assert!((ch - (h + 180.0)).abs() < 0.5);
+        assert!((cs - s).abs() < 0.01 && (cl - l).abs() < 0.01);
+        assert!(BLACK.complement() == BLACK);
+        assert!(WHITE.complement() == WHITE);
+        assert!(rgb(128, 128, 128).complement() == rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn test_grayscale() {
+        let gray = RED.grayscale();
+        assert!(gray.red == gray.green && gray.green == gray.blue);
+        assert!(gray.red == 127);
+        assert!((gray.luminance() - RED.luminance()).abs() < 0.005);
+        assert!(LIME.grayscale() == rgb(220, 220, 220));
+        assert!(rgb(90, 90, 90).grayscale() == rgb(90, 90, 90));
+        assert!(rgba(255, 0, 0, 0.5).grayscale().alpha == 0.5);
+    }
+
+    #[test]
+    fn test_nearest_named() {
+        assert!(rgb(254, 0, 1).nearest_named() == ~"red");
+        assert!(CYAN.nearest_named() == ~"aqua");
+        assert!(rgb(127, 127, 127).nearest_named() == ~"gray");
+        assert!(rgba(0, 0, 0, 0.0).nearest_named() == ~"black");
+    }
+
+    #[test]
+    fn test_all_named() {
+        let named = all_named();
+        assert!(named.len() == 148);
+        assert!(named.iter().any(|&(ref name, color)| *name == ~"red" && color == rgb(255, 0, 0)));
+        assert!(named.iter().any(|&(ref name, _)| *name == ~"rebeccapurple"));
+        for i in range(1, named.len()) {
+            let (ref prev, _) = named[i - 1];
+            let (ref next, _) = named[i];
+            assert!(*prev < *next);
+        }
+    }
+
+    #[test]
+    fn test_parse_color_result() {
+        assert!(parse_color_result("rbga(1,2,3)") == Err(UnknownKeyword));
+        assert!(parse_color_result("rgb(1,2)") == Err(BadComponentCount));
+        assert!(parse_color_result("rgb(1,x,3)") == Err(BadNumber));
+        assert!(parse_color_result("hsla(0,1,0.5,NaN)") == Err(OutOfRange));
+        assert!(parse_color_result("#ff00") == Ok(rgba(255, 255, 0, 0.0)));
+        assert!(parse_color_result("#ff000") == Err(BadComponentCount));
+        assert!(parse_color_result("#gg0000") == Err(BadNumber));
+        assert!(parse_color_result("red") == Ok(RED));
+        assert!(parse_color("rgb(1,2)") == None);
+    }
+
+    #[test]
+    fn test_parsing_truncated() {
+        for prefix in ["rgb(", "rgba(", "hsl(", "hsla(", "hsv(", "hsva("].iter() {
+            assert!(parse_color(*prefix) == None);
+            assert!(parse_color(prefix.to_owned() + ")") == None);
+            assert!(parse_color_result(*prefix) == Err(BadComponentCount));
+        }
+        assert!(parse_color("rgb(1,2,3") == None);
+        assert!(parse_color("rgb") == None);
+        assert!(parse_color("rgba") == None);
+        assert!(parse_color("hsl(1") == None);
+    }
+
+    #[test]
+    fn test_parsing_dispatch() {
+        // Four components only make sense to the rgba parser
+        assert!(parse_color_tagged("rgba(1,2,3,0.5)") == Some((rgba(1, 2, 3, 0.5), RgbaSyntax)));
+        assert!(parse_color_tagged("rgb(1,2,3)") == Some((rgb(1, 2, 3), RgbSyntax)));
+        assert!(parse_color_tagged("hsla(0,1,0.5,1)") == Some((RED, HslaSyntax)));
+        assert!(parse_color_tagged("hsva(0,1,1,1)") == Some((RED, HsvaSyntax)));
+        assert!(parse_color_result("rgba(1,2,3)") == Err(BadComponentCount));
+        assert!(explain("rgba(1,2,3,0.5)")[0] == (RgbSyntax, Err(WrongNotation)));
+    }
+
+    #[test]
+    fn test_parsing_space_separated_rgb() {
+        assert!(parse_color("rgb(255 0 0)") == Some(RED));
+        assert!(parse_color("rgb( 255  0 0 )") == Some(RED));
+        assert!(parse_color("rgb(255 0 0 / 50%)").unwrap().alpha == 0.5);
+        assert!(parse_color("rgb(255 0 0/0.25)") == Some(rgba(255, 0, 0, 0.25)));
+        assert!(parse_color("rgba(100% 0% 0% / 1)") == Some(RED));
+        assert!(parse_color_result("rgb(255 0 / 1)") == Err(BadComponentCount));
+        assert!(parse_color_result("rgb(255 0 0 0)") == Err(BadComponentCount));
+        assert!(parse_color_result("rgb(255, 0 0)") == Err(BadComponentCount));
+        assert!(parse_color_result("rgb(255 x 0)") == Err(BadNumber));
+    }
+
+    #[test]
+    fn test_parsing_strict_slash_alpha() {
+        assert!(parse_color_result("rgb(255 0 0 / red)") == Err(BadChannelValue(~"red")));
+        assert!(parse_color_result("rgb(255 0 0 / currentcolor)") ==
+                Err(BadChannelValue(~"currentcolor")));
+        assert!(parse_color_result("rgb(255 0 0 /)") == Err(BadChannelValue(~"")));
+        assert!(parse_color_result("rgb(255 0 0 / 1 / 1)") == Err(BadChannelValue(~"1 / 1")));
+        assert!(parse_color_result("hsl(0 100% 50% / red)") == Err(BadChannelValue(~"red")));
+        assert!(parse_color_result("hsla(0 100% 50% / none)") == Err(BadChannelValue(~"none")));
+        assert!(parse_color_result("hwb(0 0% 0% / red)") == Err(BadChannelValue(~"red")));
+    }
+
+    #[test]
+    fn test_parsing_hue_units() {
+        assert!(parse_color("hsl(0.3333turn,1,0.5)") == Some(LIME));
+        assert!(parse_color("hsl(120deg,1,0.5)") == Some(LIME));
+        assert!(parse_color("hsl(200grad,1,0.5)") == Some(AQUA));
+        assert!(parse_color("hsl(3.14159rad,1,0.5)") == Some(AQUA));
+        assert!(parse_color("hsla(-0.5turn,100%,50%,1)") == Some(AQUA));
+        assert!(parse_color("hsv(240deg,1,1)") == Some(BLUE));
+        assert!(parse_color("hsl(120px,1,0.5)") == None);
+        assert!(parse_color("hsl(deg,1,0.5)") == None);
+    }
+
+    #[test]
+    fn test_hwb() {
+        assert!(hwb(0.0, 0.0, 0.0) == RED);
+        assert!(hwb(0.0, 1.0, 0.0) == WHITE);
+        assert!(hwb(0.0, 0.0, 1.0) == BLACK);
+        assert!(hwb(120.0, 0.6, 0.6) == rgb(128, 128, 128));
+        assert!(hwb(240.0, 0.2, 0.4) == rgb(51, 51, 153));
+        assert!(hwba(0.0, 0.0, 0.0, 0.5) == rgba(255, 0, 0, 0.5));
+
+        assert!(parse_color("hwb(0 0% 0%)") == Some(RED));
+        assert!(parse_color("hwb(120deg 0% 0% / 50%)") == Some(rgba(0, 255, 0, 0.5)));
+        assert!(parse_color("hwb(0, 1, 0)") == Some(WHITE));
+        assert!(parse_color_tagged("hwb(0 0% 100%)") == Some((BLACK, HwbSyntax)));
+        assert!(parse_color_result("hwb(0 0%)") == Err(BadComponentCount));
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut colors = ~[WHITE, RED, BLACK, LIME, BLUE, rgba(255, 0, 0, 0.5)];
+        sort::tim_sort(colors);
+        assert!(colors == ~[BLACK, BLUE, LIME, rgba(255, 0, 0, 0.5), RED, WHITE]);
+
+        assert!(rgba(0, 0, 0, 0.2) < BLACK);
+        assert!(rgb(0, 0, 1) > rgba(0, 0, 0, 1.0));
+        let mut nan = BLACK;
+        nan.alpha = 0.0 / 0.0;
+        assert!(nan < rgba(0, 0, 0, 0.0));
+        assert!(nan.equals(&nan));
+    }
+
+    #[test]
+    fn test_hash() {
+        use super::Color;
+
+        let mut counts: HashMap<Color, uint> = HashMap::new();
+        for c in [RED, rgb(255, 0, 0), BLUE, rgba(255, 0, 0, 0.5)].iter() {
+            let n = counts.find_or_insert(*c, 0u);
+            *n += 1;
+        }
+        assert!(counts.len() == 3);
+        assert!(counts.get(&RED) == &2u);
+        assert!(counts.get(&rgba(255, 0, 0, 0.5)) == &1u);
+    }
+
+    #[test]
+    fn test_rgba_tuple() {
+        assert!(RED.to_rgba_tuple() == (255u8, 0u8, 0u8, 1.0));
+        assert!(from_rgba_tuple((255u8, 0u8, 0u8, 1.0)) == RED);
+        let c = rgba(12, 34, 56, 0.25);
+        assert!(from_rgba_tuple(c.to_rgba_tuple()) == c);
+        assert!(from_rgba_tuple((1u8, 2u8, 3u8, 2.0)) == rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_to_rgba_bytes() {
+        assert!(RED.to_rgba_bytes() == [255u8, 0, 0, 255]);
+        assert!(rgba(1, 2, 3, 0.5).to_rgba_bytes() == [1u8, 2, 3, 128]);
+        assert!(rgba(1, 2, 3, 0.0).to_rgba_bytes() == [1u8, 2, 3, 0]);
+        assert!(rgba(1, 2, 3, 0.1).to_rgba_bytes() == [1u8, 2, 3, 26]);
+    }
+
+    #[test]
+    fn test_premultiply() {
+        assert!(rgba(255, 255, 255, 0.5).premultiply() == rgba(128, 128, 128, 0.5));
+        assert!(rgba(200, 100, 0, 0.0).premultiply() == rgba(0, 0, 0, 0.0));
+        assert!(RED.premultiply() == RED);
+        assert!(RED.premultiply().unpremultiply() == RED);
+        assert!(rgb(12, 34, 56).unpremultiply() == rgb(12, 34, 56));
+
+        assert!(rgba(128, 128, 128, 0.5).unpremultiply() == rgba(255, 255, 255, 0.5));
+        assert!(rgba(100, 50, 0, 0.5).unpremultiply() == rgba(200, 100, 0, 0.5));
+        assert!(rgba(200, 0, 0, 0.5).unpremultiply() == rgba(255, 0, 0, 0.5));
+        assert!(rgba(10, 20, 30, 0.0).unpremultiply() == rgba(0, 0, 0, 0.0));
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        assert!(rgb(127, 0, 255).approx_eq(&rgb(128, 0, 254), 1, 0.0));
+        assert!(!rgb(127, 0, 255).approx_eq(&rgb(128, 0, 254), 0, 0.0));
+        assert!(!rgb(126, 0, 0).approx_eq(&rgb(128, 0, 0), 1, 0.0));
+        assert!(rgba(0, 0, 0, 0.5).approx_eq(&rgba(0, 0, 0, 0.55), 0, 0.1));
+        assert!(!rgba(0, 0, 0, 0.5).approx_eq(&rgba(0, 0, 0, 0.7), 0, 0.1));
+        assert!(RED.approx_eq(&RED, 0, 0.0));
+    }
+
+    #[test]
+    fn test_parse_modern_hsl() {
+        assert!(parse_color("hsl(120 100% 50%)") == Some(LIME));
+        assert!(parse_color("hsl(120 100% 50% / 50%)") == Some(rgba(0, 255, 0, 0.5)));
+        assert!(parse_color("hsl(120deg 100% 50% / 0.5)") == Some(rgba(0, 255, 0, 0.5)));
+        assert!(parse_color("hsla(120 100% 50% / 0.25)") == Some(rgba(0, 255, 0, 0.25)));
+        assert!(parse_color("hsla(120 100% 50%)") == Some(LIME));
+        assert!(parse_color_result("hsl(120 100%)") == Err(BadComponentCount));
+        assert!(parse_color_result("hsl(120 100% 50% / x)") == Err(BadChannelValue(~"x")));
+        assert!(parse_color("hsl(120, 100%, 50%)") == Some(LIME));
+    }
+
+    #[test]
+    fn test_hsla_out_of_range() {
+        assert!(hsl(480.0, 1.0, 0.5) == LIME);
+        assert!(hsl(-240.0, 1.0, 0.5) == LIME);
+        assert!(hsl(360.0, 1.0, 0.5) == RED);
+        assert!(hsl(0.0, 2.0, 0.5) == RED);
+        assert!(hsl(0.0, -1.0, 0.5) == hsl(0.0, 0.0, 0.5));
+        assert!(hsl(0.0, 1.0, 1.5) == WHITE);
+        assert!(hsl(0.0, 1.0, -0.5) == BLACK);
+
+        let nan = 0.0 / 0.0;
+        let inf = 1.0 / 0.0;
+        assert!(hsl(nan, 1.0, 0.5) == BLACK);
+        assert!(hsl(0.0, inf, 0.5) == BLACK);
+        assert!(hsla(0.0, 1.0, -inf, 0.5) == rgba(0, 0, 0, 0.5));
+    }
+
+    #[test]
+    fn test_hsva_out_of_range() {
+        assert!(hsv(0.0, 1.5, 1.0) == RED);
+        assert!(hsv(0.0, -0.5, 1.0) == WHITE);
+        assert!(hsv(0.0, 1.0, 2.0) == RED);
+        assert!(hsv(0.0, 1.0, -1.0) == BLACK);
+        assert!(hsv(480.0, 1.0, 1.0) == LIME);
+        assert!(hsv(0.0 / 0.0, 1.0, 1.0) == BLACK);
+        assert!(hsva(0.0, 1.0 / 0.0, 1.0, 0.5) == rgba(0, 0, 0, 0.5));
+    }
+
+    #[test]
+    fn test_parse_hsv_out_of_range() {
+        // Components past the ends clamp in hsva rather than wrapping the u8 cast
+        assert!(parse_color("hsv(0,150%,100%)") == Some(RED));
+        assert!(parse_color("hsv(0,100%,250%)") == Some(RED));
+        assert!(parse_color("hsv(0,-50%,100%)") == Some(WHITE));
+        assert!(parse_color("hsva(0,100%,-50%,0.5)") == Some(rgba(0, 0, 0, 0.5)));
+    }
+
+    #[test]
+    fn test_hwb_out_of_range() {
+        assert!(hwb(0.0, -0.5, 0.0) == RED);
+        assert!(hwb(0.0, 0.0, -0.5) == RED);
+        assert!(hwb(0.0, 1.5, 0.0) == WHITE);
+        assert!(hwb(0.0, 0.0, 1.5) == BLACK);
+        assert!(parse_color("hwb(0 -50% 0%)") == Some(RED));
+        assert!(parse_color("hwb(120 -20% -20%)") == Some(LIME));
     }
 }
@@ -6,30 +6,85 @@
 Constructs a list of css style rules from a token stream
 */
 
-// TODO: fail according to the css spec instead of failing when things
-// are not as expected
-
 use util::DataStream;
 use netsurfcss::stylesheet::{CssStylesheet, CssStylesheetParams, CssStylesheetParamsVersion1, css_stylesheet_create};
-use netsurfcss::types::CssLevel21;
+use netsurfcss::types::{CssLevel, CssLevel21, CssColor};
+use netsurfcss::errors::CssInvalid;
 use netsurfcss::CssResult;
 use wapcaplet::LwcString;
+use lwcstr_from_rust_str = wapcaplet::from_rust_string;
 use extra::url::Url;
-use netsurfcss::stylesheet::CssUrlResolutionFn;
+use std::FromStr;
+use std::str;
+use std::ascii::AsciiStr;
+use netsurfcss::stylesheet::{CssUrlResolutionFn, CssColorResolutionFn};
+use color::{Color, alpha_to_byte};
+use color::parsing::parse_color;
 
-fn default_params(url: Url) -> CssStylesheetParams {
-    let resolve: CssUrlResolutionFn = resolve_url;
-    CssStylesheetParams {
-        params_version: CssStylesheetParamsVersion1,
+/**
+Reasons a stylesheet can be rejected. These only come from the UTF-8 check
+enabled by `ParseOptions::validate_utf8`. netsurfcss checks the result codes
+of `css_stylesheet_create`, `append_data` and `data_done` itself and fails
+the task on an error, so those failures can't be reported here.
+*/
+#[deriving(Eq)]
+pub enum ParseError {
+    /// The data isn't valid UTF-8
+    InvalidEncoding,
+    /// The stream ended in the middle of a UTF-8 sequence
+    TruncatedData
+}
+
+/**
+The parts of `CssStylesheetParams` that depend on the document rather than
+on this library
+*/
+pub struct ParseOptions {
+    level: CssLevel,
+    /// Accept legacy syntax such as unitless lengths, for quirks mode documents
+    allow_quirks: bool,
+    /// Used when the data declares no charset of its own
+    charset: ~str,
+    /// Names the sheet in a set of alternate stylesheets
+    title: ~str,
+    /// Parse a declaration list, as found in a style attribute, instead of rules
+    inline_style: bool,
+    /**
+    Reject data that isn't valid UTF-8 rather than letting netsurfcss
+    substitute replacement characters. Only applies when `charset` is UTF-8.
+    */
+    validate_utf8: bool
+}
+
+pub fn default_options() -> ParseOptions {
+    ParseOptions {
         level: CssLevel21,
+        allow_quirks: false,
         charset: ~"UTF-8",
-        url: url.to_str(),
         title: ~"FIXME-css-title",
-        allow_quirks: false,
         inline_style: false,
+        validate_utf8: false
+    }
+}
+
+pub fn default_params(url: Url) -> CssStylesheetParams {
+    params_with_options(url, &default_options())
+}
+
+pub fn params_with_options(url: Url, options: &ParseOptions) -> CssStylesheetParams {
+    let resolve: CssUrlResolutionFn = resolve_url;
+    let color: CssColorResolutionFn = resolve_color;
+    CssStylesheetParams {
+        params_version: CssStylesheetParamsVersion1,
+        level: options.level,
+        charset: options.charset.clone(),
+        url: url.to_str(),
+        title: options.title.clone(),
+        allow_quirks: options.allow_quirks,
+        inline_style: options.inline_style,
         resolve: Some(resolve),
         import: None,
-        color: None,
+        color: Some(color),
         font: None,
     }
 }
@@ -39,31 +94,225 @@ fn default_params(url: Url) -> CssStylesheetParams {
 // so DataStream is an @fn which can't be sent to the lexer task.
 // So the DataStreamFactory gives the caller an opportunity to create
 // the data stream from inside the lexer task.
-pub fn parse_stylesheet(url: Url, input: DataStream) -> CssStylesheet {
-    let params = default_params(url);
+/**
+Parses with `default_options()`, which leave UTF-8 validation off, so this
+never returns `Err`. Errors inside netsurfcss fail the task instead; see
+`ParseError`.
+*/
+pub fn parse_stylesheet(url: Url, input: DataStream) -> Result<CssStylesheet, ParseError> {
+    parse_stylesheet_with_options(url, &default_options(), input)
+}
+
+/**
+Returns `Err` only when `options.validate_utf8` is set and the data isn't
+valid UTF-8. netsurfcss failures can't be surfaced and fail the task.
+*/
+pub fn parse_stylesheet_with_options(url: Url, options: &ParseOptions,
+                                     input: DataStream) -> Result<CssStylesheet, ParseError> {
+    let params = params_with_options(url, options);
     let mut sheet = css_stylesheet_create(&params);
+    let mut checker = Utf8Checker::for_options(options);
 
     loop {
         match input() {
             Some(data) => {
-                sheet.append_data(data);
+                match checker.check(data) {
+                    Ok(()) => sheet.append_data(data),
+                    Err(e) => return Err(e)
+                }
             }
             None => break
         }
     }
+    match checker.finish() {
+        Ok(()) => (),
+        Err(e) => return Err(e)
+    }
     sheet.data_done();
-    sheet
+    Ok(sheet)
+}
+
+/**
+Validates UTF-8 as it arrives in chunks, if `ParseOptions` asks for it. A
+sequence split across two chunks is held back until the rest of it is seen
+*/
+pub struct Utf8Checker {
+    priv enabled: bool,
+    priv pending: ~[u8]
+}
+
+impl Utf8Checker {
+    pub fn new(enabled: bool) -> Utf8Checker {
+        Utf8Checker {
+            enabled: enabled,
+            pending: ~[]
+        }
+    }
+
+    pub fn for_options(options: &ParseOptions) -> Utf8Checker {
+        // Other charsets are left to netsurfcss to decode
+        let utf8 = options.charset.to_ascii_lower() == ~"utf-8";
+        Utf8Checker::new(options.validate_utf8 && utf8)
+    }
+
+    pub fn check(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let buf = self.pending + data;
+        // Back up to the lead byte of the last sequence
+        let mut start = buf.len();
+        while start > 0 && buf.len() - start < 4 {
+            start -= 1;
+            if (buf[start] & 0xC0) != 0x80 { break }
+        }
+        let split = if start < buf.len()
+            && start + str::utf8_char_width(buf[start]) > buf.len() {
+            start
+        } else {
+            buf.len()
+        };
+        self.pending = buf.slice_from(split).to_owned();
+        if str::is_utf8(buf.slice_to(split)) { Ok(()) } else { Err(InvalidEncoding) }
+    }
+
+    pub fn finish(&self) -> Result<(), ParseError> {
+        if self.pending.is_empty() { Ok(()) } else { Err(TruncatedData) }
+    }
 }
 
 pub fn parse_style_attribute(url: Url, data: &str) -> CssStylesheet {
-    let mut params = default_params(url);
-    params.inline_style = true;
+    let options = ParseOptions { inline_style: true, .. default_options() };
+    let params = params_with_options(url, &options);
     let mut sheet = css_stylesheet_create(&params);
     sheet.append_data(data.as_bytes());
     sheet.data_done();
     sheet
 }
 
-fn resolve_url(_base: &str, _rel: &LwcString) -> CssResult<LwcString> {
-    fail!(~"resolving url");
+/**
+Resolves a url referenced from a stylesheet against the stylesheet's own url.
+Absolute urls are returned unchanged
+*/
+fn resolve_url(base: &str, rel: &LwcString) -> CssResult<LwcString> {
+    Ok(lwcstr_from_rust_str(join_url(base, rel.to_str())))
+}
+
+/// RFC 3986 reference resolution, for bases with a scheme and authority
+fn join_url(base_str: &str, rel: &str) -> ~str {
+    let absolute: Option<Url> = FromStr::from_str(rel);
+    let base: Option<Url> = FromStr::from_str(base_str);
+    let base = match (absolute, base) {
+        (Some(_), _) | (None, None) => return rel.to_owned(),
+        (None, Some(base)) => base
+    };
+
+    // References within the same document replace only the query or fragment
+    if rel.is_empty() || rel.starts_with("#") || rel.starts_with("?") {
+        let keep_query = !rel.starts_with("?");
+        let end = match base_str.find(|c: char| c == '#' || (c == '?' && !keep_query)) {
+            Some(i) => i,
+            None => base_str.len()
+        };
+        return base_str.slice_to(end).to_owned() + rel;
+    }
+    if rel.starts_with("//") {
+        return base.scheme + ":" + rel;
+    }
+    // Everything up to the path, e.g. http://user@host:port
+    let origin = Url { path: ~"", query: ~[], fragment: None, .. base.clone() }.to_str();
+    if rel.starts_with("/") {
+        return origin + remove_dot_segments(rel);
+    }
+
+    // Split off any query or fragment so they don't take part in path resolution
+    let path_end = match rel.find(|c: char| c == '?' || c == '#') {
+        Some(i) => i,
+        None => rel.len()
+    };
+    let dir = match base.path.rfind('/') {
+        Some(i) => base.path.slice_to(i + 1).to_owned(),
+        None => ~"/"
+    };
+    origin + remove_dot_segments(dir + rel.slice_to(path_end)) + rel.slice_from(path_end)
+}
+
+/// Collapses the `.` and `..` segments of an absolute path
+fn remove_dot_segments(path: &str) -> ~str {
+    let mut segments: ~[&str] = ~[];
+    let parts: ~[&str] = path.split_iter('/').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        match *part {
+            "." => if last { segments.push("") },
+            ".." => {
+                if segments.len() > 1 { segments.pop(); }
+                if last { segments.push("") }
+            }
+            p => segments.push(p)
+        }
+    }
+    let joined = segments.connect("/");
+    if joined.starts_with("/") { joined } else { ~"/" + joined }
+}
+
+/**
+Resolves color keywords netsurfcss doesn't know itself, so stylesheets
+accept the same names as `parsing::parse_color`
+*/
+fn resolve_color(name: &LwcString) -> CssResult<CssColor> {
+    match parse_color(name.to_str()) {
+        Some(color) => Ok(convert_color(color)),
+        None => Err(CssInvalid)
+    }
+}
+
+fn convert_color(color: Color) -> CssColor {
+    CssColor {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+        a: alpha_to_byte(color.alpha)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve_url, join_url, remove_dot_segments};
+    use lwcstr_from_rust_str = wapcaplet::from_rust_string;
+
+    static BASE: &'static str = "http://a.com/b/c/d.css?x=1#top";
+
+    #[test]
+    fn test_join_url() {
+        assert!(join_url(BASE, "../a") == ~"http://a.com/b/a");
+        assert!(join_url(BASE, "./a") == ~"http://a.com/b/c/a");
+        assert!(join_url(BASE, "a/./b/../e.png") == ~"http://a.com/b/c/a/e.png");
+        assert!(join_url(BASE, "/abs") == ~"http://a.com/abs");
+        assert!(join_url(BASE, "//host/x") == ~"http://host/x");
+        assert!(join_url(BASE, "?q") == ~"http://a.com/b/c/d.css?q");
+        assert!(join_url(BASE, "#f") == ~"http://a.com/b/c/d.css?x=1#f");
+        assert!(join_url(BASE, "") == ~"http://a.com/b/c/d.css?x=1");
+        assert!(join_url(BASE, "e.png?v=2#f") == ~"http://a.com/b/c/e.png?v=2#f");
+        assert!(join_url(BASE, "https://x.org/y") == ~"https://x.org/y");
+        // Without a usable base the reference is passed through
+        assert!(join_url("not a url", "a.png") == ~"a.png");
+    }
+
+    #[test]
+    fn test_remove_dot_segments() {
+        assert!(remove_dot_segments("/a/b/../c") == ~"/a/c");
+        assert!(remove_dot_segments("/a/./b") == ~"/a/b");
+        assert!(remove_dot_segments("/a/b/..") == ~"/a/");
+        assert!(remove_dot_segments("/a/b/.") == ~"/a/b/");
+        assert!(remove_dot_segments("/../a") == ~"/a");
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let resolved = resolve_url(BASE, &lwcstr_from_rust_str("../a.png"));
+        assert!(resolved.unwrap().to_str() == ~"http://a.com/b/a.png");
+        let resolved = resolve_url(BASE, &lwcstr_from_rust_str("#clip"));
+        assert!(resolved.unwrap().to_str() == ~"http://a.com/b/c/d.css?x=1#clip");
+    }
 }
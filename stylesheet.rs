@@ -7,9 +7,14 @@ CSS stylesheets, owned types, immutable after creation
 */
 
 use extra::url::Url;
+use std::cell::Cell;
 use util::DataStream;
-use netsurfcss::stylesheet::CssStylesheet;
-use parser::{parse_stylesheet, parse_style_attribute};
+use netsurfcss::stylesheet::{CssStylesheet, CssStylesheetParams, css_stylesheet_create};
+use parser::{parse_stylesheet, parse_stylesheet_with_options, parse_style_attribute};
+use parser::Utf8Checker;
+
+pub use parser::{default_params, ParseError, InvalidEncoding, TruncatedData};
+pub use parser::{ParseOptions, default_options, params_with_options};
 
 pub struct Stylesheet {
     inner: CssStylesheet
@@ -17,8 +22,26 @@ pub struct Stylesheet {
 
 impl Stylesheet {
     pub fn new(url: Url, input: DataStream) -> Stylesheet {
-        Stylesheet {
-            inner: parse_stylesheet(url, input)
+        match Stylesheet::parse(url, input) {
+            Ok(sheet) => sheet,
+            Err(e) => fail!(fmt!("parsing stylesheet: %?", e))
+        }
+    }
+
+    pub fn parse(url: Url, input: DataStream) -> Result<Stylesheet, ParseError> {
+        do parse_stylesheet(url, input).map_move |sheet| {
+            Stylesheet {
+                inner: sheet
+            }
+        }
+    }
+
+    pub fn with_options(url: Url, options: &ParseOptions,
+                        input: DataStream) -> Result<Stylesheet, ParseError> {
+        do parse_stylesheet_with_options(url, options, input).map_move |sheet| {
+            Stylesheet {
+                inner: sheet
+            }
         }
     }
 
@@ -28,3 +51,75 @@ impl Stylesheet {
         }
     }
 }
+
+/// Parses a stylesheet that is already in memory
+pub fn parse_stylesheet_from_str(url: Url, css: ~str) -> Stylesheet {
+    let css = Cell::new(css);
+    let input: DataStream = || {
+        if !css.is_empty() {
+            Some(css.take().into_bytes())
+        } else {
+            None
+        }
+    };
+    Stylesheet::new(url, input)
+}
+
+/// Parses the declarations of a style attribute, arriving as a stream
+pub fn parse_inline_style(url: Url, input: DataStream) -> Stylesheet {
+    let options = ParseOptions { inline_style: true, .. default_options() };
+    match Stylesheet::with_options(url, &options, input) {
+        Ok(sheet) => sheet,
+        Err(e) => fail!(fmt!("parsing inline style: %?", e))
+    }
+}
+
+/**
+Parses a stylesheet from chunks pushed by the caller as they arrive, for
+sources such as a network stream that can't be wrapped in a DataStream
+*/
+pub struct IncrementalParser {
+    priv sheet: CssStylesheet,
+    priv checker: Utf8Checker
+}
+
+impl IncrementalParser {
+    /// The url in `params` is replaced by `url`. The data isn't validated.
+    pub fn new(url: Url, params: CssStylesheetParams) -> IncrementalParser {
+        let mut params = params;
+        params.url = url.to_str();
+        IncrementalParser {
+            sheet: css_stylesheet_create(&params),
+            checker: Utf8Checker::new(false)
+        }
+    }
+
+    pub fn with_options(url: Url, options: &ParseOptions) -> IncrementalParser {
+        IncrementalParser {
+            sheet: css_stylesheet_create(&params_with_options(url, options)),
+            checker: Utf8Checker::for_options(options)
+        }
+    }
+
+    /// Rejected data is not passed on to the sheet
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        match self.checker.check(data) {
+            Ok(()) => {
+                self.sheet.append_data(data);
+                Ok(())
+            }
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn finish(self) -> Result<Stylesheet, ParseError> {
+        let IncrementalParser { sheet, checker } = self;
+        do checker.finish().map_move |_| {
+            let mut sheet = sheet;
+            sheet.data_done();
+            Stylesheet {
+                inner: sheet
+            }
+        }
+    }
+}
@@ -14,7 +14,11 @@ use units::*;
 use select::*;
 use color;
 use color::rgb;
-use stylesheet::Stylesheet;
+use color::parsing::parse_color;
+use stylesheet::{Stylesheet, IncrementalParser, default_params};
+use stylesheet::{InvalidEncoding, TruncatedData, parse_stylesheet_from_str, parse_inline_style};
+use stylesheet::{ParseOptions, default_options, params_with_options};
+use n::t::{CssLevel1, CssLevel21, CssLevel3};
 use computed::ComputedStyle;
 use complete::CompleteSelectResults;
 
@@ -106,6 +110,10 @@ impl SelectHandler<TestNode> for TestHandler {
 
 fn single_div_test(style: &str, f: &fn(&ComputedStyle)) {
     let sheet = Stylesheet::new(test_url(), style_stream(style));
+    single_div_sheet_test(sheet, f);
+}
+
+fn single_div_sheet_test(sheet: Stylesheet, f: &fn(&ComputedStyle)) {
     let mut select_ctx = SelectCtx::new();
     let handler = TestHandler::new();
     select_ctx.append_sheet(sheet, OriginAuthor);
@@ -145,6 +153,157 @@ fn test_background_color_simple() {
     }
 }
 
+#[test]
+fn test_incremental_parser() {
+    let mut parser = IncrementalParser::new(test_url(), default_params(test_url()));
+    assert!(parser.feed("div{".as_bytes()).is_ok());
+    assert!(parser.feed("color:red}".as_bytes()).is_ok());
+    do single_div_sheet_test(parser.finish().unwrap()) |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+    do single_div_test("div{color:red}") |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+}
+
+#[test]
+fn test_relative_url() {
+    let style = "div { background-image: url(images/bg.png); color: red; }";
+    do single_div_test(style) |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+}
+
+#[test]
+fn test_parse_stylesheet_from_str() {
+    let sheet = parse_stylesheet_from_str(test_url(), ~"div { color: blue }");
+    do single_div_sheet_test(sheet) |computed| {
+        assert!(computed.color() == Specified(rgb(0, 0, 255)));
+    }
+}
+
+#[test]
+fn test_color_keyword_matches_parse_color() {
+    // Newer than the keyword table built into netsurfcss
+    let style = "div { color: rebeccapurple; }";
+    do single_div_test(style) |computed| {
+        assert!(computed.color() == Specified(parse_color("rebeccapurple").unwrap()));
+    }
+}
+
+#[test]
+fn test_css_level_option() {
+    match default_params(test_url()).level {
+        CssLevel21 => (),
+        _ => fail!(~"default level should be 2.1")
+    }
+    for level in [CssLevel1, CssLevel3].iter() {
+        let options = ParseOptions { level: *level, .. default_options() };
+        match (params_with_options(test_url(), &options).level, *level) {
+            (CssLevel1, CssLevel1) | (CssLevel3, CssLevel3) => (),
+            _ => fail!(~"level not passed through")
+        }
+        let sheet = Stylesheet::with_options(test_url(), &options,
+                                             style_stream("div { color: red }"));
+        do single_div_sheet_test(sheet.unwrap()) |computed| {
+            assert!(computed.color() == Specified(rgb(255, 0, 0)));
+        }
+    }
+}
+
+#[test]
+fn test_quirks_option() {
+    assert!(!default_params(test_url()).allow_quirks);
+    let style = "div { border-top-width: 10; }";
+    let quirks = ParseOptions { allow_quirks: true, .. default_options() };
+    assert!(params_with_options(test_url(), &quirks).allow_quirks);
+    let sheet = Stylesheet::with_options(test_url(), &quirks, style_stream(style));
+    do single_div_sheet_test(sheet.unwrap()) |computed| {
+        assert!(computed.border_top_width() == Specified(CSSBorderWidthLength(Px(10.0))));
+    }
+    let sheet = Stylesheet::with_options(test_url(), &default_options(), style_stream(style));
+    do single_div_sheet_test(sheet.unwrap()) |computed| {
+        assert!(computed.border_top_width() != Specified(CSSBorderWidthLength(Px(10.0))));
+    }
+}
+
+#[test]
+fn test_title_and_charset_options() {
+    let options = ParseOptions {
+        title: ~"High contrast",
+        charset: ~"ISO-8859-1",
+        .. default_options()
+    };
+    let params = params_with_options(test_url(), &options);
+    assert!(params.title == ~"High contrast");
+    assert!(params.charset == ~"ISO-8859-1");
+    // A latin-1 "é" isn't valid UTF-8
+    let input = chunk_stream(~[bytes!("div { color: red } /* ").to_owned() + ~[0xE9u8, 0x2A, 0x2F]]);
+    let sheet = Stylesheet::with_options(test_url(), &options, input);
+    do single_div_sheet_test(sheet.unwrap()) |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+}
+
+#[test]
+fn test_parse_inline_style() {
+    assert!(!default_params(test_url()).inline_style);
+    let inline = parse_inline_style(test_url(), style_stream("color: red; border-top-width: 10px"));
+    let mut select_ctx = SelectCtx::new();
+    let handler = TestHandler::new();
+    select_ctx.append_sheet(parse_stylesheet_from_str(test_url(), ~"div { color: blue }"),
+                            OriginAuthor);
+    let dom = TestNode(@NodeData {
+        name: ~"div",
+        id: ~"id1",
+        children: ~[],
+        parent: @mut None
+    });
+    let style = select_ctx.select_style(&dom, Some(&inline), &handler);
+    let computed = style.computed_style();
+    assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    assert!(computed.border_top_width() == Specified(CSSBorderWidthLength(Px(10.0))));
+}
+
+fn chunk_stream(chunks: ~[~[u8]]) -> DataStream {
+    let chunks = @mut chunks;
+    let d: DataStream = || {
+        if chunks.is_empty() { None } else { Some(chunks.shift()) }
+    };
+    return d;
+}
+
+#[test]
+fn test_parse_malformed_stream() {
+    let stray = || chunk_stream(~[bytes!("div { color: red } /* ").to_owned(),
+                                  ~[0xFFu8, 0xFE],
+                                  bytes!(" */").to_owned()]);
+    // Without validation netsurfcss decodes around the bad bytes, as before
+    do single_div_sheet_test(Stylesheet::new(test_url(), stray())) |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+
+    let strict = ParseOptions { validate_utf8: true, .. default_options() };
+    let bad = Stylesheet::with_options(test_url(), &strict, stray());
+    assert!(bad.is_err() && bad.unwrap_err() == InvalidEncoding);
+    let cut = Stylesheet::with_options(test_url(), &strict, chunk_stream(~[~[0x64u8, 0xC3]]));
+    assert!(cut.is_err() && cut.unwrap_err() == TruncatedData);
+
+    // "é" split across two chunks is still valid
+    let split = chunk_stream(~[bytes!("div { color: red } /* ").to_owned() + ~[0xC3u8],
+                               ~[0xA9u8] + bytes!(" */").to_owned()]);
+    let sheet = Stylesheet::with_options(test_url(), &strict, split);
+    do single_div_sheet_test(sheet.unwrap()) |computed| {
+        assert!(computed.color() == Specified(rgb(255, 0, 0)));
+    }
+
+    let mut parser = IncrementalParser::with_options(test_url(), &strict);
+    assert!(parser.feed("div{color:red}".as_bytes()).is_ok());
+    assert!(parser.feed([0x80u8]) == Err(InvalidEncoding));
+    assert!(parser.feed([0xC3u8]).is_ok());
+    assert!(parser.finish().is_err());
+}
+
 #[test]
 fn test_border_top_width_px() {
     let style = "div { border-top-width: 10px; }";